@@ -0,0 +1,496 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    str::FromStr,
+};
+
+// Limbs are stored little endian in base 2^32
+const BASE_BITS: u32 = 32;
+// Largest power of 10 that fits in a limb, used to convert from and to decimal
+const DECIMAL_BASE: u32 = 1_000_000_000;
+const DECIMAL_DIGITS: usize = 9;
+
+// Arbitrary-precision signed integer.
+// The magnitude never has trailing zero limbs and zero is never negative,
+// so derived equality and hashing compare values.
+#[derive(PartialEq, Eq, Hash, Clone, Default)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid digit found in integer literal")
+    }
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+    // Demotes the value back into an i64 when it fits
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let mut abs = 0u64;
+        for (i, limb) in self.mag.iter().enumerate() {
+            abs |= (*limb as u64) << (i as u32 * BASE_BITS);
+        }
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+    pub fn from_str_radix(s: &str, radix: u32) -> Result<Self, ParseBigIntError> {
+        if radix == 10 {
+            return s.parse();
+        }
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if digits.is_empty() {
+            return Err(ParseBigIntError);
+        }
+        let mut mag = vec![];
+        for ch in digits.chars() {
+            let digit = ch.to_digit(radix).ok_or(ParseBigIntError)?;
+            mul_add_small(&mut mag, radix, digit);
+        }
+        Ok(BigInt::from_parts(negative, mag))
+    }
+    pub fn checked_div(&self, rhs: &BigInt) -> Option<BigInt> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let (quot, _) = div_rem_mag(&self.mag, &rhs.mag);
+        Some(BigInt::from_parts(self.negative != rhs.negative, quot))
+    }
+    // Remainder takes the sign of the dividend, the same as `%` on i64
+    pub fn checked_rem(&self, rhs: &BigInt) -> Option<BigInt> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let (_, rem) = div_rem_mag(&self.mag, &rhs.mag);
+        Some(BigInt::from_parts(self.negative, rem))
+    }
+
+    fn from_parts(negative: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        BigInt {
+            negative: negative && !mag.is_empty(),
+            mag,
+        }
+    }
+    fn add_signed(&self, rhs: &BigInt, rhs_negative: bool) -> BigInt {
+        if self.negative == rhs_negative {
+            return BigInt::from_parts(self.negative, add_mag(&self.mag, &rhs.mag));
+        }
+        match cmp_mag(&self.mag, &rhs.mag) {
+            Ordering::Equal => BigInt::zero(),
+            Ordering::Greater => BigInt::from_parts(self.negative, sub_mag(&self.mag, &rhs.mag)),
+            Ordering::Less => BigInt::from_parts(rhs_negative, sub_mag(&rhs.mag, &self.mag)),
+        }
+    }
+}
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut res = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, limb) in long.iter().enumerate() {
+        let sum = *limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        res.push(sum as u32);
+        carry = sum >> BASE_BITS;
+    }
+    if carry > 0 {
+        res.push(carry as u32);
+    }
+    res
+}
+// Expects |a| >= |b|
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter().enumerate() {
+        let mut diff = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << BASE_BITS;
+            borrow = 1;
+        }
+        res.push(diff as u32);
+    }
+    trim(&mut res);
+    res
+}
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut res = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let curr = res[i + j] as u64 + *x as u64 * *y as u64 + carry;
+            res[i + j] = curr as u32;
+            carry = curr >> BASE_BITS;
+        }
+        res[i + b.len()] = carry as u32;
+    }
+    trim(&mut res);
+    res
+}
+// Multiplies in place by a single limb and adds `add`, used when parsing decimal strings
+fn mul_add_small(mag: &mut Vec<u32>, mul: u32, add: u32) {
+    let mut carry = add as u64;
+    for limb in mag.iter_mut() {
+        let curr = *limb as u64 * mul as u64 + carry;
+        *limb = curr as u32;
+        carry = curr >> BASE_BITS;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+// Divides in place by a single limb and returns the remainder
+fn div_small(mag: &mut Vec<u32>, div: u32) -> u32 {
+    let mut rem = 0u64;
+    for limb in mag.iter_mut().rev() {
+        let curr = (rem << BASE_BITS) | *limb as u64;
+        *limb = (curr / div as u64) as u32;
+        rem = curr % div as u64;
+    }
+    trim(mag);
+    rem as u32
+}
+// Schoolbook binary long division, `b` must not be zero
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let mut quot = a.to_vec();
+        let rem = div_small(&mut quot, b[0]);
+        return (quot, if rem == 0 { vec![] } else { vec![rem] });
+    }
+    let mut quot = vec![0u32; a.len()];
+    let mut rem: Vec<u32> = vec![];
+    for bit in (0..a.len() * BASE_BITS as usize).rev() {
+        // rem = rem << 1 | next bit of a
+        let mut carry = (a[bit / BASE_BITS as usize] >> (bit % BASE_BITS as usize)) & 1;
+        for limb in rem.iter_mut() {
+            let next = *limb >> (BASE_BITS - 1);
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        if carry > 0 {
+            rem.push(carry);
+        }
+        if cmp_mag(&rem, b) != Ordering::Less {
+            rem = sub_mag(&rem, b);
+            quot[bit / BASE_BITS as usize] |= 1 << (bit % BASE_BITS as usize);
+        }
+    }
+    trim(&mut quot);
+    (quot, rem)
+}
+
+impl From<i64> for BigInt {
+    fn from(int: i64) -> Self {
+        let abs = int.unsigned_abs();
+        BigInt::from_parts(int < 0, vec![abs as u32, (abs >> BASE_BITS) as u32])
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|ch| ch.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let mut mag = vec![];
+        // Leading chunk is shorter so every following chunk has exactly 9 digits
+        let first = match digits.len() % DECIMAL_DIGITS {
+            0 => DECIMAL_DIGITS,
+            len => len,
+        };
+        let mut start = 0;
+        let mut end = first;
+        while start < digits.len() {
+            let chunk = &digits[start..end];
+            mul_add_small(
+                &mut mag,
+                10u32.pow(chunk.len() as u32),
+                chunk.parse().unwrap(),
+            );
+            start = end;
+            end += DECIMAL_DIGITS;
+        }
+        Ok(BigInt::from_parts(negative, mag))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = vec![];
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            chunks.push(div_small(&mut mag, DECIMAL_BASE));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Serialized as a decimal string, JSON numbers would lose precision past 2^53
+#[cfg(feature = "serde")]
+impl serde::Serialize for BigInt {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BigInt {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let digits = String::deserialize(deserializer)?;
+        digits.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq<i64> for BigInt {
+    fn eq(&self, other: &i64) -> bool {
+        self.to_i64() == Some(*other)
+    }
+}
+impl PartialOrd<i64> for BigInt {
+    fn partial_cmp(&self, other: &i64) -> Option<Ordering> {
+        Some(self.cmp(&BigInt::from(*other)))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.mag)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        self.add_signed(rhs, rhs.negative)
+    }
+}
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self.add_signed(rhs, !rhs.negative)
+    }
+}
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != rhs.negative, mul_mag(&self.mag, &rhs.mag))
+    }
+}
+// Division and remainder panic on a zero divisor, the same as the i64 operators
+impl Div for &BigInt {
+    type Output = BigInt;
+
+    fn div(self, rhs: &BigInt) -> BigInt {
+        self.checked_div(rhs).expect("attempt to divide by zero")
+    }
+}
+impl Rem for &BigInt {
+    type Output = BigInt;
+
+    fn rem(self, rhs: &BigInt) -> BigInt {
+        self.checked_rem(rhs)
+            .expect("attempt to calculate the remainder with a divisor of zero")
+    }
+}
+
+// Forwards the owned and mixed i64 variants of an operator to the `&BigInt` impl
+macro_rules! forward_binop {
+    ($imp:ident, $method:ident) => {
+        impl $imp for BigInt {
+            type Output = BigInt;
+
+            fn $method(self, rhs: BigInt) -> BigInt {
+                (&self).$method(&rhs)
+            }
+        }
+        impl $imp<i64> for BigInt {
+            type Output = BigInt;
+
+            fn $method(self, rhs: i64) -> BigInt {
+                (&self).$method(&BigInt::from(rhs))
+            }
+        }
+        impl $imp<BigInt> for i64 {
+            type Output = BigInt;
+
+            fn $method(self, rhs: BigInt) -> BigInt {
+                (&BigInt::from(self)).$method(&rhs)
+            }
+        }
+    };
+}
+forward_binop!(Add, add);
+forward_binop!(Sub, sub);
+forward_binop!(Mul, mul);
+forward_binop!(Div, div);
+forward_binop!(Rem, rem);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+    #[test]
+    fn test_parse_display() {
+        let tests = [
+            "0",
+            "7",
+            "-7",
+            "4294967296",
+            "18446744073709551616",
+            "-170141183460469231731687303715884105728",
+            "1000000000000000000000000000000",
+        ];
+        for test in tests {
+            assert_eq!(big(test).to_string(), test);
+        }
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!("".parse::<BigInt>(), Err(ParseBigIntError));
+        assert_eq!("12a".parse::<BigInt>(), Err(ParseBigIntError));
+    }
+    #[test]
+    fn test_parse_radix() {
+        assert_eq!(BigInt::from_str_radix("ff", 16), Ok(BigInt::from(255)));
+        assert_eq!(BigInt::from_str_radix("-101", 2), Ok(BigInt::from(-5)));
+        assert_eq!(
+            BigInt::from_str_radix("10000000000000000", 16).unwrap(),
+            big("18446744073709551616")
+        );
+        assert_eq!(BigInt::from_str_radix("129", 8), Err(ParseBigIntError));
+    }
+    #[test]
+    fn test_i64_conversion() {
+        for int in [0, 1, -1, i64::MAX, i64::MIN, 1 << 32] {
+            assert_eq!(BigInt::from(int).to_i64(), Some(int));
+            assert_eq!(BigInt::from(int).to_string(), int.to_string());
+        }
+        assert_eq!((BigInt::from(i64::MAX) + 1).to_i64(), None);
+        assert_eq!((BigInt::from(i64::MIN) - 1).to_i64(), None);
+    }
+    #[test]
+    fn test_arithmetic() {
+        let max = BigInt::from(i64::MAX);
+        assert_eq!((max.clone() + 1).to_string(), "9223372036854775808");
+        assert_eq!(
+            (max.clone() * max.clone()).to_string(),
+            "85070591730234615847396907784232501249"
+        );
+        assert_eq!(big("-5") + 7, 2);
+        assert_eq!(5 - big("7"), -2);
+        assert_eq!(big("5") - big("5"), BigInt::zero());
+        assert_eq!(
+            big("85070591730234615847396907784232501249") / max.clone(),
+            max
+        );
+        assert_eq!(big("-7") / 2, -3);
+        assert_eq!(big("-7") % 2, -1);
+        assert_eq!(7 % big("-2"), 1);
+        assert_eq!(
+            big("100000000000000000000000000001") % big("1000000000000000000000"),
+            1
+        );
+        assert_eq!(big("1").checked_div(&BigInt::zero()), None);
+    }
+    #[test]
+    fn test_factorial() {
+        let mut fact = BigInt::from(1);
+        for i in 1..=30 {
+            fact = fact * i;
+        }
+        assert_eq!(fact.to_string(), "265252859812191058636308480000000");
+    }
+    #[test]
+    fn test_ordering() {
+        let mut values = vec![big("10"), big("-99999999999999999999"), big("0"), big("-1")];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![big("-99999999999999999999"), big("-1"), big("0"), big("10")]
+        );
+        assert!(big("99999999999999999999") > i64::MAX);
+        assert!(big("-3") < -2);
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+// Functions every program can call without a `let`, the standard Monkey ones plus the numeric
+// conversions. This is the only list of them: the resolver binds names to these variants, and
+// an evaluator should dispatch on them rather than keep a list of its own.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Builtin {
+    Len,
+    First,
+    Last,
+    Rest,
+    Push,
+    Puts,
+    // Numeric conversions
+    Int,
+    Float,
+    Round,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Len,
+        Builtin::First,
+        Builtin::Last,
+        Builtin::Rest,
+        Builtin::Push,
+        Builtin::Puts,
+        Builtin::Int,
+        Builtin::Float,
+        Builtin::Round,
+    ];
+
+    // Name the builtin is called by in programs
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::First => "first",
+            Builtin::Last => "last",
+            Builtin::Rest => "rest",
+            Builtin::Push => "push",
+            Builtin::Puts => "puts",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+            Builtin::Round => "round",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .iter()
+            .copied()
+            .find(|builtin| builtin.name() == name)
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
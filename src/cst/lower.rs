@@ -0,0 +1,287 @@
+use super::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::token::{Position, Span, Token};
+
+// Derives the ast from a syntax tree. Nodes with syntax errors in them are left out,
+// the errors were already reported while parsing.
+pub(super) fn lower(root: &SyntaxNode) -> Program {
+    let mut lower = Lower {
+        program: Program::new(),
+        lines: LineIndex::new(&root.text()),
+    };
+    let statements = lower.block(root);
+    let mut program = lower.program;
+    program.statements = statements;
+    program
+}
+
+// Turns byte offsets back into the line and column positions the lexer reports
+struct LineIndex {
+    text: String,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex {
+            text: text.to_string(),
+            line_starts,
+        }
+    }
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+        Position {
+            offset,
+            line,
+            column: self.text[line_start..offset].chars().count() + 1,
+        }
+    }
+}
+
+struct Lower {
+    program: Program,
+    lines: LineIndex,
+}
+
+impl Lower {
+    // Span of the node's tokens, leaving out trivia at either end
+    fn span(&self, node: &SyntaxNode) -> Span {
+        let tokens: Vec<SyntaxToken> = node
+            .tokens()
+            .into_iter()
+            .filter(|token| !token.kind().is_trivia())
+            .collect();
+        let (start, end) = match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => (first.text_range().start, last.text_range().end),
+            _ => (node.text_range().start, node.text_range().start),
+        };
+        Span {
+            start: self.lines.position(start),
+            end: self.lines.position(end),
+            ..Span::default()
+        }
+    }
+
+    fn block(&mut self, node: &SyntaxNode) -> BlockStatement {
+        node.children()
+            .filter_map(|stmt| self.stmt(&stmt))
+            .collect()
+    }
+    fn stmt(&mut self, node: &SyntaxNode) -> Option<StmtId> {
+        let stmt = match node.kind() {
+            SyntaxKind::LetStmt => {
+                let name = token(node, SyntaxKind::Ident)?;
+                let (types, mut values): (Vec<_>, Vec<_>) =
+                    node.children().partition(|child| child.kind().is_type());
+                let annotation = match types.first() {
+                    Some(annotation) => Some(self.type_expr(annotation)?),
+                    None => None,
+                };
+                let value = self.expr(&values.pop()?)?;
+                Statement::Let(Ident(self.program.intern(name.text())), annotation, value)
+            }
+            SyntaxKind::ReturnStmt => match node.children().next() {
+                Some(value) => Statement::Return(Some(self.expr(&value)?)),
+                None => Statement::Return(None),
+            },
+            SyntaxKind::ExprStmt => Statement::Expr(self.expr(&node.children().next()?)?),
+            _ => return None,
+        };
+        let span = self.span(node);
+        Some(self.program.alloc_stmt(stmt, span))
+    }
+    fn expr(&mut self, node: &SyntaxNode) -> Option<ExprId> {
+        let mut children = node.children();
+        let expr = match node.kind() {
+            SyntaxKind::IdentExpr => {
+                let name = token(node, SyntaxKind::Ident)?;
+                Expr::Ident(Ident(self.program.intern(name.text())))
+            }
+            SyntaxKind::Literal => {
+                let text = significant_tokens(node).first()?.text().to_string();
+                let literal = match Lexer::new(&text).next_token() {
+                    Token::Int(int) => Literal::Int(int),
+                    Token::BigInt(int) => Literal::BigInt(int),
+                    Token::Float(float) => Literal::Float(float),
+                    Token::Boolean(boolean) => Literal::Boolean(boolean),
+                    _ => return None,
+                };
+                Expr::Literal(literal)
+            }
+            SyntaxKind::PrefixExpr => {
+                let prefix = match significant_tokens(node).first()?.kind() {
+                    SyntaxKind::Bang => Prefix::Not,
+                    _ => Prefix::Minus,
+                };
+                Expr::Prefix(prefix, self.expr(&children.next()?)?)
+            }
+            SyntaxKind::InfixExpr => {
+                let left = self.expr(&children.next()?)?;
+                let right = self.expr(&children.next()?)?;
+                // The operator is the only token directly in the node
+                let infix = match significant_tokens(node).first()?.kind() {
+                    SyntaxKind::Plus => Infix::Plus,
+                    SyntaxKind::Minus => Infix::Minus,
+                    SyntaxKind::Slash => Infix::Divide,
+                    SyntaxKind::Asterisk => Infix::Multiply,
+                    SyntaxKind::Eq => Infix::Equal,
+                    SyntaxKind::NotEq => Infix::NotEqual,
+                    SyntaxKind::Lt => Infix::LessThan,
+                    SyntaxKind::Gt => Infix::GreaterThan,
+                    _ => return None,
+                };
+                Expr::Infix(left, infix, right)
+            }
+            // Like `Parser`, the inner expression takes the span of the parentheses
+            SyntaxKind::ParenExpr => {
+                let inner = self.expr(&children.next()?)?;
+                let span = self.span(node);
+                self.program.set_span(inner, span);
+                return Some(inner);
+            }
+            SyntaxKind::IfExpr => {
+                let cond = self.expr(&children.next()?)?;
+                let consequence = self.block(&children.next()?);
+                let alternative = children.next().map(|block| self.block(&block));
+                Expr::If {
+                    cond,
+                    consequence,
+                    alternative,
+                }
+            }
+            _ => return None,
+        };
+        let span = self.span(node);
+        Some(self.program.alloc_expr(expr, span))
+    }
+    fn type_expr(&mut self, node: &SyntaxNode) -> Option<TypeExpr> {
+        let mut children = node.children();
+        let ty = match node.kind() {
+            SyntaxKind::NamedType => {
+                let name = token(node, SyntaxKind::Ident)?;
+                TypeExpr::Named(Ident(self.program.intern(name.text())))
+            }
+            SyntaxKind::ArrayType => TypeExpr::Array(Box::new(self.type_expr(&children.next()?)?)),
+            SyntaxKind::HashType => {
+                let key = self.type_expr(&children.next()?)?;
+                let value = self.type_expr(&children.next()?)?;
+                TypeExpr::Hash(Box::new(key), Box::new(value))
+            }
+            // The return type is the last child, after the parameters
+            SyntaxKind::FnType => {
+                let mut types = children
+                    .map(|child| self.type_expr(&child))
+                    .collect::<Option<Vec<TypeExpr>>>()?;
+                let ret = types.pop()?;
+                TypeExpr::Function(types, Box::new(ret))
+            }
+            _ => return None,
+        };
+        Some(ty)
+    }
+}
+
+// Tokens directly in `node` that are not trivia
+fn significant_tokens(node: &SyntaxNode) -> Vec<SyntaxToken> {
+    node.children_with_tokens()
+        .into_iter()
+        .filter_map(|element| match element {
+            SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+            _ => None,
+        })
+        .collect()
+}
+fn token(node: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+    significant_tokens(node)
+        .into_iter()
+        .find(|token| token.kind() == kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cst::parse;
+    use crate::lexer::Lexer;
+    use crate::parser::ast::Program;
+    use crate::parser::testing::Rng;
+    use crate::parser::Parser;
+    use crate::token::Span;
+
+    // Every span in the arena, in an order independent of allocation
+    fn spans(program: &Program) -> Vec<Span> {
+        let mut spans: Vec<Span> = program
+            .expr_ids()
+            .map(|id| program.span(id))
+            .chain(program.stmt_ids().map(|id| program.span(id)))
+            .collect();
+        spans.sort_by_key(|span| (span.start.offset, span.end.offset));
+        spans
+    }
+
+    #[test]
+    fn test_lower_matches_parser() {
+        let tests = [
+            "let x = 5;\nlet y = 10n; let z = 2.5e3",
+            "return;\nreturn x\n",
+            "return return",
+            "  -a * (b + c) / !d;  // trailing\n",
+            "1 - 2 - 3 == (1 - (2 - 3)) != false",
+            "if (x < y) {\n  /* c */ x\n} else {\n  return y;\n}\n-1",
+            "let z = if ((a)) { 1 } else { if (b) {} };",
+            "let café = 1;\n\tcafé > 0",
+            "let x: int = 1; let f : fn([int], {string: bool}) -> fn() -> any = g",
+        ];
+        for input in tests {
+            let parse = parse(input);
+            assert_eq!(parse.get_errors(), Vec::<String>::new(), "{}", input);
+            let mut parser = Parser::new(Lexer::new(input));
+            let expected = parser.parse_program();
+            let program = parse.to_program();
+            assert_eq!(program, expected, "{}", input);
+            assert_eq!(spans(&program), spans(&expected), "{}", input);
+        }
+    }
+
+    // Both parsers accept the same token strings and agree on the program they parse
+    #[test]
+    fn test_random_tokens_match_parser() {
+        for input in [
+            "else < + / 3n }",
+            "let a = 1;\n)\nlet b = 2;",
+            "{ * [ } true : = , * -",
+        ] {
+            assert!(!parse(input).get_errors().is_empty(), "{}", input);
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert!(!parser.get_errors().is_empty(), "{}", input);
+        }
+        let words = [
+            "x", "3", "3n", "2.5", "true", "=", "+", "-", "!", "*", "/", "<", ">", "==", "!=",
+            "->", ",", ":", ";", "(", ")", "{", "}", "[", "]", "fn", "let", "if", "else", "return",
+            "\n",
+        ];
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut failures = vec![];
+        for _ in 0..20_000 {
+            let len = 1 + rng.below(8);
+            let input = (0..len)
+                .map(|_| rng.pick(&words))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let parse = parse(&input);
+            let mut parser = Parser::new(Lexer::new(&input));
+            let expected = parser.parse_program();
+            let (cst_ok, ok) = (
+                parse.get_errors().is_empty(),
+                parser.get_errors().is_empty(),
+            );
+            if cst_ok != ok || ok && parse.to_program() != expected {
+                failures.push(input);
+            }
+        }
+        assert_eq!(failures, Vec::<String>::new());
+    }
+}
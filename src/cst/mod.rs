@@ -0,0 +1,476 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::parser::ast::Program;
+use crate::parser::ParseErrors;
+
+pub use self::parser::parse;
+mod lower;
+mod parser;
+
+// Kinds of the tokens and nodes of the concrete syntax tree
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum SyntaxKind {
+    // Trivia
+    Whitespace,
+    Comment,
+    // Tokens
+    Ident,
+    // Both 64-bit and big integer literals
+    Int,
+    Float,
+    Boolean,
+    Assign,
+    Plus,
+    Minus,
+    Bang,
+    Asterisk,
+    Slash,
+    Lt,
+    Gt,
+    Eq,
+    NotEq,
+    Arrow,
+    Comma,
+    Colon,
+    Semicolon,
+    Lparen,
+    Rparen,
+    Lbrace,
+    Rbrace,
+    Lbracket,
+    Rbracket,
+    FnKw,
+    LetKw,
+    IfKw,
+    ElseKw,
+    ReturnKw,
+    // Text the lexer could not make sense of
+    Illegal,
+    // End of input, never stored in a tree
+    Eof,
+    // Nodes
+    Root,
+    LetStmt,
+    ReturnStmt,
+    ExprStmt,
+    IdentExpr,
+    Literal,
+    PrefixExpr,
+    InfixExpr,
+    ParenExpr,
+    IfExpr,
+    Block,
+    // Type annotations
+    NamedType,
+    ArrayType,
+    HashType,
+    FnType,
+    // Tokens skipped while recovering from a syntax error
+    Error,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(self, SyntaxKind::Whitespace | SyntaxKind::Comment)
+    }
+    pub fn is_type(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::NamedType
+                | SyntaxKind::ArrayType
+                | SyntaxKind::HashType
+                | SyntaxKind::FnType
+        )
+    }
+}
+
+// Green tree: immutable and position independent, so unchanged subtrees can be shared between
+// versions of a file. Every byte of the source is in exactly one token, trivia included.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    len: usize,
+    children: Vec<GreenElement>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: &str) -> Self {
+        GreenToken {
+            kind,
+            text: text.to_string(),
+        }
+    }
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let len = children.iter().map(GreenElement::len).sum();
+        GreenNode {
+            kind,
+            len,
+            children,
+        }
+    }
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+    // Length of the source text covered, in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+    // Copy of this node with one child swapped, the other children are shared
+    pub fn replace_child(&self, index: usize, child: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = child;
+        GreenNode::new(self.kind, children)
+    }
+    fn write_text(&self, out: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(out),
+                GreenElement::Token(token) => out.push_str(&token.text),
+            }
+        }
+    }
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            GreenElement::Node(node) => node.kind,
+            GreenElement::Token(token) => token.kind,
+        }
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.len,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<GreenNode> for GreenElement {
+    fn from(node: GreenNode) -> Self {
+        GreenElement::Node(Rc::new(node))
+    }
+}
+impl From<GreenToken> for GreenElement {
+    fn from(token: GreenToken) -> Self {
+        GreenElement::Token(Rc::new(token))
+    }
+}
+
+// Red tree: a view of a green tree that knows each element's parent and absolute offset.
+// Built lazily while walking down, so cloning and navigating are cheap.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    // Position among the parent's children
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: GreenNode) -> Self {
+        SyntaxNode(Rc::new(NodeData {
+            green: Rc::new(green),
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+    pub fn green(&self) -> &GreenNode {
+        &self.0.green
+    }
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+    // Byte range in the source, including any trivia inside the node
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len
+    }
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.0.green.len);
+        self.0.green.write_text(&mut text);
+        text
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = Vec::with_capacity(self.0.green.children.len());
+        for (index, child) in self.0.green.children.iter().enumerate() {
+            elements.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    parent: Some(self.clone()),
+                    index,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    parent: self.clone(),
+                    index,
+                    offset,
+                }),
+            });
+            offset += child.len();
+        }
+        elements
+    }
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+    }
+    // Every token below this node in source order, trivia included
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = vec![];
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    // Replaces this node, returning the new root. Everything outside the path from the root
+    // to this node is shared with the old tree, formatting included.
+    pub fn replace_with(&self, replacement: GreenNode) -> GreenNode {
+        match &self.0.parent {
+            None => replacement,
+            Some(parent) => {
+                let green = parent
+                    .green()
+                    .replace_child(self.0.index, replacement.into());
+                parent.replace_with(green)
+            }
+        }
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+    // Replaces this token, returning the new root
+    pub fn replace_with(&self, replacement: GreenToken) -> GreenNode {
+        let green = self
+            .parent
+            .green()
+            .replace_child(self.index, replacement.into());
+        self.parent.replace_with(green)
+    }
+}
+
+// `{:?}` prints the kind and range, `{:#?}` the whole subtree one element per line
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{:?}@{:?}", self.kind(), self.text_range());
+        }
+        let mut depth = 0;
+        write_tree(f, self, &mut depth)
+    }
+}
+fn write_tree(f: &mut fmt::Formatter, node: &SyntaxNode, depth: &mut usize) -> fmt::Result {
+    writeln!(f, "{:indent$}{:?}", "", node, indent = *depth * 2)?;
+    *depth += 1;
+    for element in node.children_with_tokens() {
+        match element {
+            SyntaxElement::Node(node) => write_tree(f, &node, depth)?,
+            SyntaxElement::Token(token) => {
+                writeln!(f, "{:indent$}{:?}", "", token, indent = *depth * 2)?
+            }
+        }
+    }
+    *depth -= 1;
+    Ok(())
+}
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}@{:?} {:?}",
+            self.kind(),
+            self.text_range(),
+            self.text()
+        )
+    }
+}
+
+// Result of parsing a source into a concrete syntax tree
+pub struct Parse {
+    green: GreenNode,
+    errors: ParseErrors,
+}
+
+impl Parse {
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+    pub fn get_errors(&self) -> ParseErrors {
+        self.errors.clone()
+    }
+    // The same tree `Parser::parse_program` builds, spans included
+    pub fn to_program(&self) -> Program {
+        lower::lower(&self.syntax())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let tests = [
+            "",
+            "  \n\t ",
+            "let x = 1 + 2; // comment\n",
+            "let x = if (a) {\n  /* block */ b\n} else { -c };\n\n",
+            // Syntax and lexical errors keep their text too
+            "let = ; ) fn 0b12 @ (1 +",
+            "let x = 1 /* unterminated",
+            "a\0b c",
+        ];
+        for input in tests {
+            let parse = parse(input);
+            let root = parse.syntax();
+            assert_eq!(root.text(), input);
+            assert_eq!(root.text_range(), 0..input.len());
+            let text: String = root.tokens().iter().map(SyntaxToken::text).collect();
+            assert_eq!(text, input);
+        }
+    }
+    #[test]
+    fn test_tree() {
+        let parse = parse("let x = -a; // c\n");
+        assert!(parse.get_errors().is_empty());
+        let expected = r#"Root@0..17
+  LetStmt@0..11
+    LetKw@0..3 "let"
+    Whitespace@3..4 " "
+    Ident@4..5 "x"
+    Whitespace@5..6 " "
+    Assign@6..7 "="
+    Whitespace@7..8 " "
+    PrefixExpr@8..10
+      Minus@8..9 "-"
+      IdentExpr@9..10
+        Ident@9..10 "a"
+    Semicolon@10..11 ";"
+  Whitespace@11..12 " "
+  Comment@12..16 "// c"
+  Whitespace@16..17 "\n"
+"#;
+        assert_eq!(format!("{:#?}", parse.syntax()), expected);
+    }
+    #[test]
+    fn test_errors() {
+        let parse = parse("let = 5;\n)");
+        assert_eq!(
+            parse.get_errors(),
+            vec![
+                "Expected next token to be Ident, got Assign instead",
+                "Expected an expression, got Assign instead",
+                "Expected an expression, got Rparen instead",
+            ]
+        );
+        let kinds: Vec<SyntaxKind> = parse.syntax().children().map(|n| n.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SyntaxKind::LetStmt,
+                SyntaxKind::Error,
+                SyntaxKind::ExprStmt,
+                SyntaxKind::Error
+            ]
+        );
+    }
+    #[test]
+    fn test_replace_keeps_formatting() {
+        let input = "let  x = 1;   // one\nif (x) {\n    x  +  2\n}\n";
+        let root = parse(input).syntax();
+        let let_stmt = root.children().next().unwrap();
+        let name = let_stmt
+            .tokens()
+            .into_iter()
+            .find(|token| token.kind() == SyntaxKind::Ident)
+            .unwrap();
+        let green = name.replace_with(GreenToken::new(SyntaxKind::Ident, "y"));
+        assert_eq!(
+            SyntaxNode::new_root(green.clone()).text(),
+            "let  y = 1;   // one\nif (x) {\n    x  +  2\n}\n"
+        );
+
+        // Statements off the edited path are shared with the old tree
+        let if_stmt = |root: &GreenNode| match root.children().iter().rev().nth(1) {
+            Some(GreenElement::Node(node)) => node.clone(),
+            _ => panic!("expected the if statement"),
+        };
+        assert_eq!(if_stmt(&green).kind(), SyntaxKind::ExprStmt);
+        assert!(Rc::ptr_eq(&if_stmt(root.green()), &if_stmt(&green)));
+    }
+}
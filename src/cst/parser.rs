@@ -0,0 +1,367 @@
+use super::{GreenElement, GreenNode, GreenToken, Parse, SyntaxKind};
+use crate::lexer::Lexer;
+use crate::parser::ast::Precedence;
+use crate::parser::ParseErrors;
+use crate::token::Token;
+
+// Parses `input` into a lossless syntax tree. Unlike `Parser` nothing is dropped on errors,
+// tokens that fit nowhere are kept in `Error` nodes.
+pub fn parse(input: &str) -> Parse {
+    let (tokens, errors) = lex(input);
+    let mut parser = CstParser {
+        tokens,
+        pos: 0,
+        builder: Builder::default(),
+        errors,
+    };
+    parser.builder.start_node(SyntaxKind::Root);
+    while parser.current() != SyntaxKind::Eof {
+        parser.statement();
+    }
+    parser.eat_trivia();
+    parser.builder.finish_node();
+    Parse {
+        green: parser.builder.finish(),
+        errors: parser.errors,
+    }
+}
+
+// Lexes with comments kept and turns the gaps between tokens into whitespace tokens
+fn lex(input: &str) -> (Vec<(SyntaxKind, &str)>, ParseErrors) {
+    let mut tokens = vec![];
+    let mut end = 0;
+    let mut lexer = Lexer::new(input).with_comments();
+    for token in lexer.by_ref() {
+        if token.token == Token::Eof {
+            break;
+        }
+        let start = token.span.start.offset;
+        if start > end {
+            tokens.push(gap(&input[end..start]));
+        }
+        end = token.span.end.offset;
+        tokens.push((token_kind(&token.token), &input[start..end]));
+    }
+    // Trailing whitespace, or whatever follows a NUL character the lexer stopped at
+    if end < input.len() {
+        tokens.push(gap(&input[end..]));
+    }
+    let errors = lexer
+        .take_errors()
+        .iter()
+        .map(|err| err.to_string())
+        .collect();
+    (tokens, errors)
+}
+fn gap(text: &str) -> (SyntaxKind, &str) {
+    if text.trim().is_empty() {
+        (SyntaxKind::Whitespace, text)
+    } else {
+        (SyntaxKind::Illegal, text)
+    }
+}
+
+fn token_kind(token: &Token) -> SyntaxKind {
+    match token {
+        Token::Illegal => SyntaxKind::Illegal,
+        Token::Eof => SyntaxKind::Eof,
+        Token::Comment(_) => SyntaxKind::Comment,
+        Token::Ident(_) => SyntaxKind::Ident,
+        Token::Int(_) | Token::BigInt(_) => SyntaxKind::Int,
+        Token::Float(_) => SyntaxKind::Float,
+        Token::Boolean(_) => SyntaxKind::Boolean,
+        Token::Assign => SyntaxKind::Assign,
+        Token::Plus => SyntaxKind::Plus,
+        Token::Minus => SyntaxKind::Minus,
+        Token::Bang => SyntaxKind::Bang,
+        Token::Asterisk => SyntaxKind::Asterisk,
+        Token::Slash => SyntaxKind::Slash,
+        Token::Lt => SyntaxKind::Lt,
+        Token::Gt => SyntaxKind::Gt,
+        Token::Eq => SyntaxKind::Eq,
+        Token::NotEq => SyntaxKind::NotEq,
+        Token::Arrow => SyntaxKind::Arrow,
+        Token::Comma => SyntaxKind::Comma,
+        Token::Colon => SyntaxKind::Colon,
+        Token::Semicolon => SyntaxKind::Semicolon,
+        Token::Lparen => SyntaxKind::Lparen,
+        Token::Rparen => SyntaxKind::Rparen,
+        Token::Lbrace => SyntaxKind::Lbrace,
+        Token::Rbrace => SyntaxKind::Rbrace,
+        Token::Lbracket => SyntaxKind::Lbracket,
+        Token::Rbracket => SyntaxKind::Rbracket,
+        Token::Function => SyntaxKind::FnKw,
+        Token::Let => SyntaxKind::LetKw,
+        Token::If => SyntaxKind::IfKw,
+        Token::Else => SyntaxKind::ElseKw,
+        Token::Return => SyntaxKind::ReturnKw,
+    }
+}
+
+fn infix_precedence(kind: SyntaxKind) -> Option<Precedence> {
+    match kind {
+        SyntaxKind::Eq | SyntaxKind::NotEq => Some(Precedence::Equals),
+        SyntaxKind::Lt | SyntaxKind::Gt => Some(Precedence::LessGreater),
+        SyntaxKind::Plus | SyntaxKind::Minus => Some(Precedence::Sum),
+        SyntaxKind::Slash | SyntaxKind::Asterisk => Some(Precedence::Product),
+        _ => None,
+    }
+}
+
+// Assembles green nodes from a flat list of finished children
+#[derive(Default)]
+struct Builder {
+    // Kind of each open node and where its children start in `children`
+    parents: Vec<(SyntaxKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+// Position a node can later be started at, to wrap children already added
+#[derive(Clone, Copy)]
+struct Checkpoint(usize);
+
+impl Builder {
+    fn start_node(&mut self, kind: SyntaxKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+    fn start_node_at(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        self.parents.push((kind, checkpoint.0));
+    }
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+    fn token(&mut self, kind: SyntaxKind, text: &str) {
+        self.children.push(GreenToken::new(kind, text).into());
+    }
+    fn finish_node(&mut self) {
+        let (kind, start) = self.parents.pop().expect("no node to finish");
+        let children = self.children.split_off(start);
+        self.children.push(GreenNode::new(kind, children).into());
+    }
+    fn finish(mut self) -> GreenNode {
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) if self.children.is_empty() => {
+                std::rc::Rc::unwrap_or_clone(node)
+            }
+            _ => panic!("unfinished syntax tree"),
+        }
+    }
+}
+
+// Recursive descent parser following the same grammar as `Parser`. Trivia is attached to the
+// innermost node open when it is reached, before the node of the next token starts.
+struct CstParser<'a> {
+    tokens: Vec<(SyntaxKind, &'a str)>,
+    pos: usize,
+    builder: Builder,
+    errors: ParseErrors,
+}
+
+impl<'a> CstParser<'a> {
+    // Kind of the next token that is not trivia
+    fn current(&self) -> SyntaxKind {
+        self.tokens[self.pos..]
+            .iter()
+            .map(|(kind, _)| *kind)
+            .find(|kind| !kind.is_trivia())
+            .unwrap_or(SyntaxKind::Eof)
+    }
+    fn eat_trivia(&mut self) {
+        while let Some((kind, text)) = self.tokens.get(self.pos) {
+            if !kind.is_trivia() {
+                break;
+            }
+            self.builder.token(*kind, text);
+            self.pos += 1;
+        }
+    }
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if let Some((kind, text)) = self.tokens.get(self.pos) {
+            self.builder.token(*kind, text);
+            self.pos += 1;
+        }
+    }
+    fn eat(&mut self, kind: SyntaxKind) -> bool {
+        if self.current() != kind {
+            return false;
+        }
+        self.bump();
+        true
+    }
+    fn expect(&mut self, kind: SyntaxKind) -> bool {
+        if self.eat(kind) {
+            return true;
+        }
+        let msg = format!(
+            "Expected next token to be {:?}, got {:?} instead",
+            kind,
+            self.current()
+        );
+        self.errors.push(msg);
+        false
+    }
+    fn start_node(&mut self, kind: SyntaxKind) {
+        self.eat_trivia();
+        self.builder.start_node(kind);
+    }
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.eat_trivia();
+        self.builder.checkpoint()
+    }
+    fn finish_node(&mut self) {
+        self.builder.finish_node();
+    }
+
+    fn statement(&mut self) {
+        match self.current() {
+            SyntaxKind::LetKw => {
+                self.start_node(SyntaxKind::LetStmt);
+                self.bump();
+                if self.expect(SyntaxKind::Ident)
+                    && (!self.eat(SyntaxKind::Colon) || self.type_expr())
+                    && self.expect(SyntaxKind::Assign)
+                {
+                    self.expr_or_error(Precedence::Lowest);
+                }
+                self.eat(SyntaxKind::Semicolon);
+                self.finish_node();
+            }
+            SyntaxKind::ReturnKw => {
+                self.start_node(SyntaxKind::ReturnStmt);
+                self.bump();
+                self.expr(Precedence::Lowest);
+                self.eat(SyntaxKind::Semicolon);
+                self.finish_node();
+            }
+            // An empty statement, `Parser` skips it silently
+            SyntaxKind::Semicolon => self.bump(),
+            _ => {
+                let checkpoint = self.checkpoint();
+                if self.expr(Precedence::Lowest) {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::ExprStmt);
+                    self.eat(SyntaxKind::Semicolon);
+                    self.finish_node();
+                } else {
+                    self.expr_error();
+                    self.start_node(SyntaxKind::Error);
+                    self.bump();
+                    self.finish_node();
+                }
+            }
+        }
+    }
+    fn block(&mut self) {
+        if self.current() != SyntaxKind::Lbrace {
+            self.expect(SyntaxKind::Lbrace);
+            return;
+        }
+        self.start_node(SyntaxKind::Block);
+        self.bump();
+        while !matches!(self.current(), SyntaxKind::Rbrace | SyntaxKind::Eof) {
+            self.statement();
+        }
+        self.expect(SyntaxKind::Rbrace);
+        self.finish_node();
+    }
+
+    // Returns false without consuming anything when no type starts here
+    fn type_expr(&mut self) -> bool {
+        let kind = match self.current() {
+            SyntaxKind::Ident => SyntaxKind::NamedType,
+            SyntaxKind::Lbracket => SyntaxKind::ArrayType,
+            SyntaxKind::Lbrace => SyntaxKind::HashType,
+            SyntaxKind::FnKw => SyntaxKind::FnType,
+            other => {
+                let msg = format!("Expected a type, got {:?} instead", other);
+                self.errors.push(msg);
+                return false;
+            }
+        };
+        self.start_node(kind);
+        self.bump();
+        match kind {
+            SyntaxKind::ArrayType => {
+                self.type_expr();
+                self.expect(SyntaxKind::Rbracket);
+            }
+            SyntaxKind::HashType => {
+                self.type_expr();
+                self.expect(SyntaxKind::Colon);
+                self.type_expr();
+                self.expect(SyntaxKind::Rbrace);
+            }
+            SyntaxKind::FnType => {
+                self.expect(SyntaxKind::Lparen);
+                if self.current() != SyntaxKind::Rparen {
+                    while self.type_expr() && self.eat(SyntaxKind::Comma) {}
+                }
+                self.expect(SyntaxKind::Rparen);
+                self.expect(SyntaxKind::Arrow);
+                self.type_expr();
+            }
+            _ => {}
+        }
+        self.finish_node();
+        true
+    }
+
+    fn expr_error(&mut self) {
+        let msg = format!("Expected an expression, got {:?} instead", self.current());
+        self.errors.push(msg);
+    }
+    fn expr_or_error(&mut self, precedence: Precedence) {
+        if !self.expr(precedence) {
+            self.expr_error();
+        }
+    }
+    // Returns false without consuming anything when no expression starts here
+    fn expr(&mut self, precedence: Precedence) -> bool {
+        let checkpoint = self.checkpoint();
+        if !self.prefix_expr() {
+            return false;
+        }
+        while let Some(next) = infix_precedence(self.current()) {
+            if precedence >= next {
+                break;
+            }
+            self.builder
+                .start_node_at(checkpoint, SyntaxKind::InfixExpr);
+            self.bump();
+            self.expr_or_error(next);
+            self.finish_node();
+        }
+        true
+    }
+    fn prefix_expr(&mut self) -> bool {
+        let kind = match self.current() {
+            SyntaxKind::Ident => SyntaxKind::IdentExpr,
+            SyntaxKind::Int | SyntaxKind::Float | SyntaxKind::Boolean => SyntaxKind::Literal,
+            SyntaxKind::Bang | SyntaxKind::Minus => SyntaxKind::PrefixExpr,
+            SyntaxKind::Lparen => SyntaxKind::ParenExpr,
+            SyntaxKind::IfKw => SyntaxKind::IfExpr,
+            _ => return false,
+        };
+        self.start_node(kind);
+        self.bump();
+        match kind {
+            SyntaxKind::PrefixExpr => self.expr_or_error(Precedence::Prefix),
+            SyntaxKind::ParenExpr => {
+                self.expr_or_error(Precedence::Lowest);
+                self.expect(SyntaxKind::Rparen);
+            }
+            SyntaxKind::IfExpr => {
+                self.expect(SyntaxKind::Lparen);
+                self.expr_or_error(Precedence::Lowest);
+                self.expect(SyntaxKind::Rparen);
+                self.block();
+                if self.eat(SyntaxKind::ElseKw) {
+                    self.block();
+                }
+            }
+            _ => {}
+        }
+        self.finish_node();
+        true
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use crate::token::Span;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// A message about a range of the source, rendered with the offending line underlined
+#[derive(PartialEq, Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // Identifies the check that produced it, e.g. a lint id
+    pub code: Option<String>,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span,
+            help: None,
+        }
+    }
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message, span)
+        }
+    }
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    // Renders in the style of rustc, `source` has to be the text the span points into:
+    //
+    // warning[unused-let]: x is never used
+    //  --> main.mk:1:5
+    //   |
+    // 1 | let x = 5;
+    //   | ^^^^^^^^^^
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let mut out = String::new();
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match &self.code {
+            Some(code) => write!(out, "{}[{}]: {}", severity, code, self.message).unwrap(),
+            None => write!(out, "{}: {}", severity, self.message).unwrap(),
+        }
+        let start = self.span.start;
+        let gutter = " ".repeat(start.line.to_string().len());
+        write!(out, "\n{}--> {}:{}", gutter, file_name, start).unwrap();
+
+        if let Some(line) = source.lines().nth(start.line.saturating_sub(1)) {
+            // Keep tabs in the indentation so the carets line up with the text above them
+            let indent: String = line
+                .chars()
+                .take(start.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let line_chars = line.chars().count();
+            let end_column = if self.span.end.line == start.line {
+                self.span.end.column
+            } else {
+                line_chars + 1
+            };
+            let carets = end_column.saturating_sub(start.column).max(1);
+            write!(out, "\n{} |", gutter).unwrap();
+            write!(out, "\n{} | {}", start.line, line).unwrap();
+            write!(out, "\n{} | {}{}", gutter, indent, "^".repeat(carets)).unwrap();
+        }
+        if let Some(help) = &self.help {
+            write!(out, "\n{} = help: {}", gutter, help).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Position;
+
+    fn span(line: usize, column: usize, end_line: usize, end_column: usize) -> Span {
+        Span {
+            start: Position {
+                offset: 0,
+                line,
+                column,
+            },
+            end: Position {
+                offset: 0,
+                line: end_line,
+                column: end_column,
+            },
+            ..Span::default()
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "let a = 1;\n\tlet bb = a == a;\n";
+        let diagnostic = Diagnostic::warning("comparing a with itself", span(2, 11, 2, 17))
+            .with_code("self-comparison")
+            .with_help("this is always true");
+        let expected = "warning[self-comparison]: comparing a with itself
+ --> main.mk:2:11
+  |
+2 | \tlet bb = a == a;
+  | \t         ^^^^^^
+  = help: this is always true";
+        assert_eq!(diagnostic.render(source, "main.mk"), expected);
+    }
+    #[test]
+    fn test_render_multi_line() {
+        let source = "if (x) {\n  y\n}";
+        let diagnostic = Diagnostic::error("bad if", span(1, 1, 3, 2));
+        let expected = "error: bad if
+ --> main.mk:1:1
+  |
+1 | if (x) {
+  | ^^^^^^^^";
+        assert_eq!(diagnostic.render(source, "main.mk"), expected);
+    }
+}
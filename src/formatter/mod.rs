@@ -0,0 +1,487 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::printer::{infix_precedence, infix_str, let_str, prefix_str, Parens};
+use crate::parser::{ParseErrors, Parser};
+use crate::token::{Position, Span, SpannedToken, Token};
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct FormatConfig {
+    // Statements longer than this are broken at their top level operators
+    pub max_width: usize,
+    // Spaces per indentation level
+    pub indent: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            max_width: 100,
+            indent: 4,
+        }
+    }
+}
+
+// Reparses `input` and prints it in the canonical style, keeping its comments. Fails when the
+// input does not parse, or the output would not parse back to the same program.
+pub fn format_source(input: &str, config: &FormatConfig) -> Result<String, ParseErrors> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut comments = vec![];
+    let mut tokens = vec![];
+    for token in Lexer::new(input).with_comments() {
+        match token.token {
+            Token::Comment(text) => comments.push((token.span, text)),
+            _ => tokens.push(token),
+        }
+    }
+    let mut formatter = Formatter {
+        input,
+        program: &program,
+        config,
+        braces: match_braces(&tokens),
+        tokens,
+        comments,
+        next_comment: 0,
+        code_end: 0,
+        misplaced: None,
+        last_line: None,
+        out: String::new(),
+        depth: 0,
+    };
+    formatter.block_body(&program.statements, input.len());
+    if let Some(pos) = formatter.misplaced {
+        return Err(vec![format!(
+            "Comment at {} is inside an expression and would move, it was left as is",
+            pos
+        )]);
+    }
+    // Never hand out a file that means something else, the input is kept instead
+    let mut parser = Parser::new(Lexer::new(&formatter.out));
+    let formatted = parser.parse_program();
+    if !parser.get_errors().is_empty() || formatted != program {
+        return Err(vec![String::from(
+            "Formatting would change the program, it was left as is",
+        )]);
+    }
+    Ok(formatter.out)
+}
+
+// Maps the offset of every `{` to the offset of its matching `}`
+fn match_braces(tokens: &[SpannedToken]) -> HashMap<usize, usize> {
+    let mut braces = HashMap::new();
+    let mut open = vec![];
+    for token in tokens {
+        match token.token {
+            Token::Lbrace => open.push(token.span.start.offset),
+            Token::Rbrace => {
+                if let Some(start) = open.pop() {
+                    braces.insert(start, token.span.start.offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    braces
+}
+
+struct Formatter<'a> {
+    input: &'a str,
+    program: &'a Program,
+    config: &'a FormatConfig,
+    // Tokens without comments, used to find the braces around blocks
+    tokens: Vec<SpannedToken<'a>>,
+    braces: HashMap<usize, usize>,
+    comments: Vec<(Span, Cow<'a, str>)>,
+    next_comment: usize,
+    // Offset after the code emitted last, a comment before it sits inside an expression
+    code_end: usize,
+    // Start of the first comment that could not stay where it was
+    misplaced: Option<Position>,
+    // Source line the last emitted statement or comment ended on, None at the start of a block
+    last_line: Option<usize>,
+    out: String,
+    depth: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn indent(&mut self) {
+        let width = self.depth * self.config.indent;
+        self.out.extend(std::iter::repeat_n(' ', width));
+    }
+    // Keeps a single blank line where the source had one or more
+    fn blank_line(&mut self, start_line: usize) {
+        if let Some(last_line) = self.last_line {
+            if start_line > last_line + 1 {
+                self.out.push('\n');
+            }
+        }
+    }
+    // Emits the comments starting before `offset` on their own lines
+    fn comments_before(&mut self, offset: usize) {
+        while let Some((span, text)) = self.comments.get(self.next_comment) {
+            if span.start.offset >= offset {
+                break;
+            }
+            let (span, text) = (*span, text.clone());
+            self.next_comment += 1;
+            if span.start.offset < self.code_end {
+                self.misplaced.get_or_insert(span.start);
+            }
+            self.blank_line(span.start.line);
+            self.indent();
+            self.out.push_str(&text);
+            self.out.push('\n');
+            self.last_line = Some(span.end.line);
+        }
+    }
+    // Appends the comments that follow a statement on the line it ends on, up to the `}` at `end`
+    fn trailing_comments(&mut self, span: Span, end: usize) {
+        while let Some((comment, text)) = self.comments.get(self.next_comment) {
+            if comment.start.line != span.end.line
+                || comment.start.offset < span.end.offset
+                || comment.start.offset >= end
+            {
+                break;
+            }
+            self.out.push(' ');
+            self.out.push_str(text);
+            self.last_line = Some(comment.end.line);
+            self.next_comment += 1;
+        }
+    }
+
+    // Emits statements one per line at the current depth, `end` is the offset closing the block
+    fn block_body(&mut self, stmts: &[StmtId], end: usize) {
+        self.last_line = None;
+        for (i, id) in stmts.iter().enumerate() {
+            let span = self.program.span(*id);
+            self.comments_before(span.start.offset);
+            self.blank_line(span.start.line);
+            self.last_line = Some(span.end.line);
+            self.indent();
+            let start = self.out.len();
+            self.stmt(*id, stmts.get(i + 1).copied());
+            if self.too_long(start) {
+                self.out.truncate(start);
+                self.stmt_wrapped(*id);
+            }
+            self.code_end = span.end.offset;
+            self.trailing_comments(span, end);
+            self.out.push('\n');
+        }
+        self.comments_before(end);
+    }
+    fn too_long(&self, start: usize) -> bool {
+        let line_start = self.out[..start].rfind('\n').map_or(0, |i| i + 1);
+        let text = &self.out[line_start..];
+        !text.contains('\n') && text.chars().count() > self.config.max_width
+    }
+
+    fn stmt(&mut self, id: StmtId, next: Option<StmtId>) {
+        match &self.program[id] {
+            Statement::Blank => {}
+            Statement::Let(name, annotation, value) => {
+                self.out
+                    .push_str(&let_str(self.program, name, annotation.as_ref()));
+                self.expr(*value, Precedence::Lowest);
+                self.out.push(';');
+            }
+            Statement::Return(Some(value)) => {
+                self.out.push_str("return ");
+                self.expr(*value, Precedence::Lowest);
+                self.out.push(';');
+            }
+            Statement::Return(None) => self.out.push_str("return;"),
+            Statement::Expr(expr) => {
+                self.expr(*expr, Precedence::Lowest);
+                // `if` statements read better without a semicolon, but one is still needed when the
+                // next statement starts with `-` as it would otherwise continue the expression
+                let is_if = matches!(self.program[*expr], Expr::If { .. });
+                let next_is_minus = next.is_some_and(|next| {
+                    self.program
+                        .display_node(next, Parens::Minimal)
+                        .to_string()
+                        .starts_with('-')
+                });
+                if !is_if || next_is_minus {
+                    self.out.push(';');
+                }
+            }
+        }
+    }
+    // Statement whose value is broken before each operator of its top level chain
+    fn stmt_wrapped(&mut self, id: StmtId) {
+        let (prefix, value) = match &self.program[id] {
+            Statement::Let(name, annotation, value) => {
+                (let_str(self.program, name, annotation.as_ref()), *value)
+            }
+            Statement::Return(Some(value)) => (String::from("return "), *value),
+            Statement::Expr(value) => (String::new(), *value),
+            _ => return self.stmt(id, None),
+        };
+        let Expr::Infix(_, infix, _) = &self.program[value] else {
+            return self.stmt(id, None);
+        };
+        let precedence = infix_precedence(infix);
+        // Operators are left associative, so the chain runs down the left operands
+        let mut rest = vec![];
+        let mut first = value;
+        while let Expr::Infix(left, infix, right) = &self.program[first] {
+            if infix_precedence(infix) != precedence {
+                break;
+            }
+            rest.push((infix, *right));
+            first = *left;
+        }
+        self.out.push_str(&prefix);
+        self.expr(first, precedence);
+        self.depth += 1;
+        for (infix, right) in rest.into_iter().rev() {
+            self.out.push('\n');
+            self.indent();
+            self.out.push_str(infix_str(infix));
+            self.out.push(' ');
+            self.expr(right, precedence.next());
+        }
+        self.depth -= 1;
+        self.out.push(';');
+    }
+
+    // Same rules as the minimal parentheses printer, except blocks span several lines
+    fn expr(&mut self, id: ExprId, context: Precedence) {
+        match &self.program[id] {
+            Expr::Ident(_) => {
+                let text = self.program.display_node(id, Parens::Minimal).to_string();
+                self.out.push_str(&text);
+            }
+            // Keeps the spelling of the source, like `0xFF` or `1_000`
+            Expr::Literal(_) => {
+                let text = self.literal_text(id);
+                self.out.push_str(text);
+            }
+            Expr::Prefix(prefix, right) => {
+                let wrap = context > Precedence::Prefix;
+                self.open(wrap);
+                self.out.push_str(prefix_str(prefix));
+                self.expr(*right, Precedence::Prefix);
+                self.close(wrap);
+            }
+            Expr::Infix(left, infix, right) => {
+                let precedence = infix_precedence(infix);
+                let wrap = context > precedence;
+                self.open(wrap);
+                self.expr(*left, precedence);
+                self.out.push_str(&format!(" {} ", infix_str(infix)));
+                self.expr(*right, precedence.next());
+                self.close(wrap);
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.out.push_str("if (");
+                self.expr(*cond, Precedence::Lowest);
+                self.out.push_str(") ");
+                let cond_end = self.program.span(*cond).end.offset;
+                let close = self.block(consequence, cond_end);
+                if let Some(alternative) = alternative {
+                    self.out.push_str(" else ");
+                    self.block(alternative, close);
+                }
+            }
+        }
+    }
+    // Source text of the literal token, its span also covers any parentheses around it
+    fn literal_text(&self, id: ExprId) -> &'a str {
+        let start = self.program.span(id).start.offset;
+        let first = self
+            .tokens
+            .partition_point(|token| token.span.start.offset < start);
+        let token = self.tokens[first..]
+            .iter()
+            .find(|token| token.token != Token::Lparen)
+            .expect("a literal has a token");
+        &self.input[token.span.start.offset..token.span.end.offset]
+    }
+    // Emits a braced block whose `{` is the first one after `after`, returns the offset of its `}`
+    fn block(&mut self, stmts: &BlockStatement, after: usize) -> usize {
+        let open = self
+            .tokens
+            .iter()
+            .find(|token| token.token == Token::Lbrace && token.span.start.offset >= after)
+            .map(|token| token.span.start.offset);
+        let close = open
+            .and_then(|open| self.braces.get(&open).copied())
+            .unwrap_or(after);
+        if stmts.is_empty() && !self.has_comment_before(close) {
+            self.out.push_str("{}");
+            return close;
+        }
+        self.out.push_str("{\n");
+        if let Some(open) = open {
+            self.code_end = open + 1;
+        }
+        let last_line = self.last_line;
+        self.depth += 1;
+        self.block_body(stmts, close);
+        self.depth -= 1;
+        self.last_line = last_line;
+        self.indent();
+        self.out.push('}');
+        close
+    }
+    fn has_comment_before(&self, offset: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|(span, _)| span.start.offset < offset)
+    }
+    fn open(&mut self, wrap: bool) {
+        if wrap {
+            self.out.push('(');
+        }
+    }
+    fn close(&mut self, wrap: bool) {
+        if wrap {
+            self.out.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::testing::parse;
+
+    fn format(input: &str) -> String {
+        format_source(input, &FormatConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let input = r#"let   x=1+2*3
+let y :{string:[int]}= (x) ;if(x<y){x}else{ return   y };
+
+
+-x
+"#;
+        let expected = r#"let x = 1 + 2 * 3;
+let y: {string: [int]} = x;
+if (x < y) {
+    x;
+} else {
+    return y;
+};
+
+-x;
+"#;
+        assert_eq!(format(input), expected);
+        assert_eq!(parse(&format(input)), parse(input));
+    }
+    #[test]
+    fn test_format_comments() {
+        let input = r#"// header comment
+
+let x = 1; // trailing
+/* before y */ let y = if (x) {
+  // inside the block
+  x /* after x */
+  // end of block
+} else {}
+// end of file"#;
+        let expected = r#"// header comment
+
+let x = 1; // trailing
+/* before y */
+let y = if (x) {
+    // inside the block
+    x; /* after x */
+    // end of block
+} else {};
+// end of file
+"#;
+        assert_eq!(format(input), expected);
+    }
+    #[test]
+    fn test_format_wraps_long_lines() {
+        let input =
+            "let total = first_value * 2 + second_value - (third_value + fourth_value) + 1;";
+        let config = FormatConfig {
+            max_width: 40,
+            ..FormatConfig::default()
+        };
+        let expected = r#"let total = first_value * 2
+    + second_value
+    - (third_value + fourth_value)
+    + 1;
+"#;
+        let formatted = format_source(input, &config).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(parse(&formatted), parse(input));
+        assert_eq!(format_source(&formatted, &config).unwrap(), formatted);
+    }
+    #[test]
+    fn test_format_is_idempotent() {
+        let input = r#"
+        if (a) { if (b) { 1 } else { -2 } };
+        -a;
+        let z = !(a == b) != (c < d); // compare
+        return;
+        "#;
+        let once = format(input);
+        assert_eq!(format(&once), once);
+        assert_eq!(parse(&once), parse(input));
+        assert!(once.contains("};\n-a;"));
+    }
+    #[test]
+    fn test_format_keeps_literal_spelling() {
+        let input = "let x = (0xFF)+1_000 * 2.50e3 +  10n ;";
+        assert_eq!(format(input), "let x = 0xFF + 1_000 * 2.50e3 + 10n;\n");
+    }
+    #[test]
+    fn test_format_comment_in_expression() {
+        for (input, pos) in [
+            ("let x = 1 + /* two */ 2;", "1:13"),
+            ("let x = 1 +\n  // two\n  2;\nlet y = x;", "2:3"),
+            ("if (c) /* then */ { a }", "1:8"),
+            ("if (c) { a } // else\nelse { b }", "1:14"),
+            ("let x = if (c) { a } /* value */;", "1:22"),
+        ] {
+            let msg = format!(
+                "Comment at {} is inside an expression and would move, it was left as is",
+                pos
+            );
+            assert_eq!(
+                format_source(input, &FormatConfig::default()),
+                Err(vec![msg]),
+                "{}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn test_format_parse_error() {
+        for input in [
+            "let = 5;",
+            "let a = 1;\n)\nlet b = 2;",
+            "{ * [ } true : = , * -",
+            // Chars that start no token
+            "let x = \"a\" @ 2;\nlet y = 🐒;",
+            "let x = 1 # comment",
+        ] {
+            assert!(
+                format_source(input, &FormatConfig::default()).is_err(),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(format("return\nreturn 2.5"), "return;\nreturn 2.5;\n");
+    }
+}
@@ -1,121 +1,554 @@
-use crate::token::{get_keyword, Token};
+use std::{borrow::Cow, fmt, mem};
+
+use crate::bigint::BigInt;
+use crate::token::{get_keyword, FileId, Position, Span, SpannedToken, Token};
+
+pub use self::reader::ReaderLexer;
+mod reader;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum LexError {
+    // Integer literal that does not fit into an i64, `pos` is the position of its first digit
+    IntegerOverflow {
+        literal: String,
+        pos: Position,
+    },
+    // Float literal too large to be represented by an f64, e.g. `1e5000`
+    FloatOverflow {
+        literal: String,
+        pos: Position,
+    },
+    // Number literal that stops in the middle, e.g. an exponent without digits in `1e+`
+    MalformedNumber {
+        literal: String,
+        pos: Position,
+    },
+    // Digit that is not valid for the radix of the literal, `pos` is the position of the digit
+    InvalidDigit {
+        literal: String,
+        digit: char,
+        radix: u32,
+        pos: Position,
+    },
+    // Block comment still open at the end of the input, `pos` is the position of its `/*`
+    UnterminatedComment {
+        pos: Position,
+    },
+    // Char that starts no token, e.g. `@` or `#`
+    IllegalChar {
+        ch: char,
+        pos: Position,
+    },
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::IntegerOverflow { literal, pos } => write!(
+                f,
+                "Integer literal {} at {} is out of range for a 64-bit integer, use {}n for a big integer",
+                literal, pos, literal
+            ),
+            LexError::FloatOverflow { literal, pos } => write!(
+                f,
+                "Float literal {} at {} is out of range for a 64-bit float",
+                literal, pos
+            ),
+            LexError::MalformedNumber { literal, pos } => {
+                write!(f, "Malformed number literal {} at {}", literal, pos)
+            }
+            LexError::InvalidDigit {
+                literal,
+                digit,
+                radix,
+                pos,
+            } => write!(
+                f,
+                "Invalid digit {:?} for a base {} literal in {} at {}",
+                digit, radix, literal, pos
+            ),
+            LexError::UnterminatedComment { pos } => {
+                write!(f, "Unterminated block comment starting at {}", pos)
+            }
+            LexError::IllegalChar { ch, pos } => {
+                write!(f, "Illegal character {:?} at {}", ch, pos)
+            }
+        }
+    }
+}
+
+// Where skipping whitespace and comments stopped
+#[derive(Clone, Copy, Debug)]
+enum Trivia {
+    Space,
+    LineComment,
+    // `start` is the position of the outermost `/*`, for reporting it unterminated
+    BlockComment { depth: usize, start: Position },
+}
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input: &'a str,
+    // Offset of `input` in the whole source, non-zero when lexing a chunk of a stream
+    base_offset: usize,
+    file: FileId,
     pos: usize,
     next_pos: usize,
-    ch: u8,
+    ch: char,
+    // Position of `ch`, columns count chars rather than bytes
+    line: usize,
+    column: usize,
+    errors: Vec<LexError>,
+    // Return comments as `Token::Comment` instead of skipping them
+    keep_comments: bool,
+    // Set once the Eof token was yielded by the iterator
+    finished: bool,
 }
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Lexer::resume(
+            input,
+            Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        )
+    }
+    // Lexes `input` as if it started at `start` in a larger source
+    fn resume(input: &'a str, start: Position) -> Self {
         let mut lexer = Lexer {
             input,
+            base_offset: start.offset,
+            file: FileId::default(),
             pos: 0,
             next_pos: 0,
-            ch: 0,
+            ch: '\0',
+            line: start.line,
+            column: start.column,
+            errors: vec![],
+            keep_comments: false,
+            finished: false,
         };
         lexer.read_char();
         lexer
     }
+    // Tags the spans of the lexed tokens with `file`
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+    // Emits comments as tokens, for tools like formatters that need to preserve them
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
     // Helper function to traverse the input string
     fn read_char(&mut self) {
-        if self.next_pos >= self.input.len() {
-            self.ch = 0;
-        } else {
-            self.ch = self.input.as_bytes()[self.next_pos];
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if self.next_pos > 0 {
+            self.column += 1;
         }
+        self.ch = self.input[self.next_pos.min(self.input.len())..]
+            .chars()
+            .next()
+            .unwrap_or('\0');
         self.pos = self.next_pos;
-        self.next_pos += 1;
+        self.next_pos += self.ch.len_utf8();
+    }
+    fn curr_position(&self) -> Position {
+        Position {
+            offset: self.base_offset + self.pos,
+            line: self.line,
+            column: self.column,
+        }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Token<'a> {
+        self.next_spanned_token().token
+    }
+    pub fn next_spanned_token(&mut self) -> SpannedToken<'a> {
         self.skip_whitespace();
+        let start = self.curr_position();
+        let token = self.read_token();
+        SpannedToken {
+            token,
+            span: Span {
+                file: self.file,
+                start,
+                end: self.curr_position(),
+            },
+        }
+    }
+    // Reads the token starting at the current char, whitespace has to be skipped already
+    fn read_token(&mut self) -> Token<'a> {
         let token = match self.ch {
-            b'=' => {
-                if self.peek_char() == b'=' {
+            '=' => {
+                if self.peek_char() == '=' {
                     self.read_char();
                     Token::Eq
                 } else {
                     Token::Assign
                 }
             }
-            b'+' => Token::Plus,
-            b'-' => Token::Minus,
-            b'!' => {
-                if self.peek_char() == b'=' {
+            '+' => Token::Plus,
+            '-' => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    Token::Arrow
+                } else {
+                    Token::Minus
+                }
+            }
+            '!' => {
+                if self.peek_char() == '=' {
                     self.read_char();
                     Token::NotEq
                 } else {
                     Token::Bang
                 }
             }
-            b'/' => Token::Slash,
-            b'*' => Token::Asterisk,
-            b'<' => Token::Lt,
-            b'>' => Token::Gt,
-            b';' => Token::Semicolon,
-            b',' => Token::Comma,
-            b'(' => Token::Lparen,
-            b')' => Token::Rparen,
-            b'{' => Token::Lbrace,
-            b'}' => Token::Rbrace,
-            0 => Token::Eof,
+            '/' => match self.peek_char() {
+                // Only reachable when comments are kept, otherwise they are skipped as whitespace
+                '/' => return Token::Comment(Cow::Borrowed(self.read_line_comment())),
+                '*' => return Token::Comment(Cow::Borrowed(self.read_block_comment())),
+                _ => Token::Slash,
+            },
+            '*' => Token::Asterisk,
+            '<' => Token::Lt,
+            '>' => Token::Gt,
+            ';' => Token::Semicolon,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '(' => Token::Lparen,
+            ')' => Token::Rparen,
+            '{' => Token::Lbrace,
+            '}' => Token::Rbrace,
+            '[' => Token::Lbracket,
+            ']' => Token::Rbracket,
+            // Stay on the end of the input so the Eof span is empty
+            '\0' => return Token::Eof,
             _ => {
                 // Need to early return because we want to treat the blob of text as 1 identifier
-                if is_letter(self.ch) {
+                if is_ident_start(self.ch) {
                     return self.read_identifier();
                 };
                 if is_number(self.ch) {
                     return self.read_number();
                 }
+                self.errors.push(LexError::IllegalChar {
+                    ch: self.ch,
+                    pos: self.curr_position(),
+                });
                 Token::Illegal
             }
         };
         self.read_char();
         token
     }
-    fn read_identifier(&mut self) -> Token {
+    fn read_identifier(&mut self) -> Token<'a> {
         let start = self.pos;
-        while is_letter(self.ch) {
+        while is_ident_continue(self.ch) {
+            self.read_char();
+        }
+        let input = self.input;
+        get_keyword(&input[start..self.pos])
+    }
+    fn read_number(&mut self) -> Token<'a> {
+        let start = self.pos;
+        let start_pos = self.curr_position();
+        if self.ch == '0' {
+            let radix = match self.peek_char() {
+                'x' | 'X' => 16,
+                'o' | 'O' => 8,
+                'b' | 'B' => 2,
+                _ => 10,
+            };
+            if radix != 10 {
+                return self.read_radix_number(radix);
+            }
+        }
+        self.read_digits();
+        let mut is_float = false;
+        // A dot only starts a fraction when a digit follows it
+        if self.ch == '.' && is_number(self.peek_char()) {
+            is_float = true;
             self.read_char();
+            self.read_digits();
+        }
+        if self.ch == 'e' || self.ch == 'E' {
+            is_float = true;
+            self.read_char();
+            if self.ch == '+' || self.ch == '-' {
+                self.read_char();
+            }
+            if !is_number(self.ch) {
+                self.errors.push(LexError::MalformedNumber {
+                    literal: String::from(&self.input[start..self.pos]),
+                    pos: start_pos,
+                });
+                return Token::Illegal;
+            }
+            self.read_digits();
         }
         let literal = &self.input[start..self.pos];
-        get_keyword(literal)
+        let digits = literal.replace('_', "");
+        if is_float {
+            let float = digits.parse::<f64>().unwrap();
+            // Parsing rounds literals past f64::MAX to infinity instead of failing
+            if float.is_infinite() {
+                self.errors.push(LexError::FloatOverflow {
+                    literal: String::from(literal),
+                    pos: start_pos,
+                });
+                return Token::Illegal;
+            }
+            return Token::Float(float);
+        }
+        // The `n` suffix opts into an arbitrary-precision integer, unless it starts a word
+        if self.ch == 'n' && is_ident_continue(self.peek_char()) {
+            while is_ident_continue(self.ch) {
+                self.read_char();
+            }
+            self.errors.push(LexError::MalformedNumber {
+                literal: String::from(&self.input[start..self.pos]),
+                pos: start_pos,
+            });
+            return Token::Illegal;
+        }
+        if self.ch == 'n' {
+            self.read_char();
+            return Token::BigInt(digits.parse::<BigInt>().unwrap());
+        }
+        self.int_token(literal, &digits, 10, start_pos)
     }
-    fn read_number(&mut self) -> Token {
+    // Reads `0x`, `0o` and `0b` prefixed integers, the current char is the leading `0`
+    fn read_radix_number(&mut self, radix: u32) -> Token<'a> {
         let start = self.pos;
-        while is_number(self.ch) {
+        let start_pos = self.curr_position();
+        self.read_char();
+        self.read_char();
+        let digits_start = self.pos;
+        // Consume every alphanumeric char so `0b102` or `0xFG` is reported as one bad literal,
+        // only stopping for a trailing `n` suffix
+        let mut invalid = None;
+        while self.ch.is_ascii_alphanumeric() || self.ch == '_' {
+            if self.ch == 'n' && !is_ident_continue(self.peek_char()) {
+                break;
+            }
+            if invalid.is_none() && self.ch != '_' && !self.ch.is_digit(radix) {
+                invalid = Some((self.ch, self.curr_position()));
+            }
             self.read_char();
         }
         let literal = &self.input[start..self.pos];
-        Token::Int(literal.parse::<i64>().unwrap())
+        if let Some((digit, pos)) = invalid {
+            self.errors.push(LexError::InvalidDigit {
+                literal: String::from(literal),
+                digit,
+                radix,
+                pos,
+            });
+            return Token::Illegal;
+        }
+        let digits = self.input[digits_start..self.pos].replace('_', "");
+        if digits.is_empty() {
+            self.errors.push(LexError::MalformedNumber {
+                literal: String::from(literal),
+                pos: start_pos,
+            });
+            return Token::Illegal;
+        }
+        if self.ch == 'n' {
+            self.read_char();
+            return Token::BigInt(BigInt::from_str_radix(&digits, radix).unwrap());
+        }
+        self.int_token(literal, &digits, radix, start_pos)
+    }
+    fn int_token(&mut self, literal: &str, digits: &str, radix: u32, start: Position) -> Token<'a> {
+        match i64::from_str_radix(digits, radix) {
+            Ok(int) => Token::Int(int),
+            // The digits are all valid for the radix so the only way parsing fails is overflow
+            Err(_) => {
+                self.errors.push(LexError::IntegerOverflow {
+                    literal: String::from(literal),
+                    pos: start,
+                });
+                Token::Illegal
+            }
+        }
+    }
+
+    // Digits may be separated by underscores, e.g. `1_000_000`
+    fn read_digits(&mut self) {
+        while is_number(self.ch) || self.ch == '_' {
+            self.read_char();
+        }
+    }
+
+    pub fn get_errors(&self) -> Vec<LexError> {
+        self.errors.clone()
+    }
+    // Hands the errors collected so far to the caller, so they are only reported once
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        mem::take(&mut self.errors)
     }
 
     fn skip_whitespace(&mut self) {
-        while self.ch == b' ' || self.ch == b'\t' || self.ch == b'\n' || self.ch == b'\r' {
-            self.read_char()
+        self.skip_trivia(Trivia::Space, false);
+    }
+    // Skips whitespace and comments, starting inside a comment for any `state` but `Space`.
+    // Returns `None` at the start of a token, or the state to resume from in the next chunk when
+    // a `partial` input runs out first.
+    fn skip_trivia(&mut self, mut state: Trivia, partial: bool) -> Option<Trivia> {
+        loop {
+            if self.at_end(partial) {
+                if partial {
+                    return Some(state);
+                }
+                if let Trivia::BlockComment { start, .. } = state {
+                    self.errors
+                        .push(LexError::UnterminatedComment { pos: start });
+                }
+                return None;
+            }
+            state = match state {
+                Trivia::Space => match (self.ch, self.peek_char()) {
+                    (' ' | '\t' | '\n' | '\r', _) => {
+                        self.read_char();
+                        Trivia::Space
+                    }
+                    ('/', '/') if !self.keep_comments => Trivia::LineComment,
+                    ('/', '*') if !self.keep_comments => {
+                        let start = self.curr_position();
+                        self.read_char();
+                        self.read_char();
+                        Trivia::BlockComment { depth: 1, start }
+                    }
+                    _ => return None,
+                },
+                // The newline is left for `Space` to skip
+                Trivia::LineComment if self.ch == '\n' => Trivia::Space,
+                Trivia::LineComment => {
+                    self.read_char();
+                    Trivia::LineComment
+                }
+                Trivia::BlockComment { depth, start } => match self.step_block_comment(depth) {
+                    0 => Trivia::Space,
+                    depth => Trivia::BlockComment { depth, start },
+                },
+            };
         }
     }
-    fn peek_char(&mut self) -> u8 {
-        if self.next_pos >= self.input.len() {
-            0
+    // Whether the input ran out. A `partial` input also stops before its last char, which could
+    // be the first half of a `//`, `/*` or `*/` continued in the next chunk.
+    fn at_end(&self, partial: bool) -> bool {
+        if partial {
+            self.next_pos >= self.input.len()
         } else {
-            self.input.as_bytes()[self.next_pos]
+            self.pos >= self.input.len()
         }
     }
+    // Reads a `//` comment up to, but not including, the end of the line
+    fn read_line_comment(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.ch != '\n' && self.ch != '\0' {
+            self.read_char();
+        }
+        let input = self.input;
+        &input[start..self.pos]
+    }
+    // Reads a `/* */` comment, block comments can be nested
+    fn read_block_comment(&mut self) -> &'a str {
+        let start = self.pos;
+        let start_pos = self.curr_position();
+        self.read_char();
+        self.read_char();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.ch == '\0' {
+                self.errors
+                    .push(LexError::UnterminatedComment { pos: start_pos });
+                break;
+            }
+            depth = self.step_block_comment(depth);
+        }
+        let input = self.input;
+        &input[start..self.pos]
+    }
+    // Moves past the current char of a block comment nested `depth` deep, or past both chars of
+    // a `/*` or `*/`, and returns the new depth
+    fn step_block_comment(&mut self, depth: usize) -> usize {
+        let depth = match (self.ch, self.peek_char()) {
+            ('/', '*') => {
+                self.read_char();
+                depth + 1
+            }
+            ('*', '/') => {
+                self.read_char();
+                depth - 1
+            }
+            _ => depth,
+        };
+        self.read_char();
+        depth
+    }
+    fn peek_char(&mut self) -> char {
+        self.input[self.next_pos.min(self.input.len())..]
+            .chars()
+            .next()
+            .unwrap_or('\0')
+    }
 }
 
-fn is_number(ch: u8) -> bool {
-    (b'0'..=b'9').contains(&ch)
+// Yields every token including the final `Token::Eof`, errors are collected in the lexer
+impl<'a> Iterator for Lexer<'a> {
+    type Item = SpannedToken<'a>;
+
+    fn next(&mut self) -> Option<SpannedToken<'a>> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_spanned_token();
+        self.finished = token.token == Token::Eof;
+        Some(token)
+    }
+}
+
+// Lexes the whole input, failing with the first error
+pub fn tokenize(input: &str) -> Result<Vec<SpannedToken<'_>>, LexError> {
+    let mut lexer = Lexer::new(input);
+    let tokens = lexer.by_ref().collect();
+    match lexer.take_errors().into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(tokens),
+    }
+}
+
+fn is_number(ch: char) -> bool {
+    ch.is_ascii_digit()
+}
+// Identifiers follow Unicode XID, with `_` also allowed as the first char
+fn is_ident_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
 }
-fn is_letter(ch: u8) -> bool {
-    (b'a'..=b'z').contains(&ch) || (b'A'..=b'Z').contains(&ch) || ch == b'_'
+fn is_ident_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::token::Token;
+    fn pos(offset: usize, line: usize, column: usize) -> Position {
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
     #[test]
     fn test_lexer() {
         let input = "=+(){},;";
@@ -148,39 +581,39 @@ mod tests {
         "#;
         let tests = [
             Token::Let,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Assign,
             Token::Int(5),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::Assign,
             Token::Int(10),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::Assign,
             Token::Function,
             Token::Lparen,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Comma,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::Rparen,
             Token::Lbrace,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Plus,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::Semicolon,
             Token::Rbrace,
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("result")),
+            Token::Ident("result".into()),
             Token::Assign,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::Lparen,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Comma,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::Rparen,
             Token::Semicolon,
             Token::Eof,
@@ -213,4 +646,392 @@ mod tests {
         }
         //
     }
+    #[test]
+    fn test_type_tokens() {
+        let input = "let f: fn([int], {string: bool}) -> int = a->b - -c;";
+        let tests = [
+            Token::Let,
+            Token::Ident("f".into()),
+            Token::Colon,
+            Token::Function,
+            Token::Lparen,
+            Token::Lbracket,
+            Token::Ident("int".into()),
+            Token::Rbracket,
+            Token::Comma,
+            Token::Lbrace,
+            Token::Ident("string".into()),
+            Token::Colon,
+            Token::Ident("bool".into()),
+            Token::Rbrace,
+            Token::Rparen,
+            Token::Arrow,
+            Token::Ident("int".into()),
+            Token::Assign,
+            Token::Ident("a".into()),
+            Token::Arrow,
+            Token::Ident("b".into()),
+            Token::Minus,
+            Token::Minus,
+            Token::Ident("c".into()),
+            Token::Semicolon,
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in tests {
+            assert_eq!(lexer.next_token(), expected);
+        }
+    }
+    #[test]
+    fn test_integer_overflow() {
+        let input = "9223372036854775807; 9223372036854775808;";
+        let tests = [
+            Token::Int(i64::MAX),
+            Token::Semicolon,
+            Token::Illegal,
+            Token::Semicolon,
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::IntegerOverflow {
+                literal: String::from("9223372036854775808"),
+                pos: pos(21, 1, 22),
+            }]
+        );
+    }
+    #[test]
+    fn test_big_integer() {
+        let input = "5n 9223372036854775808n + 1";
+        let tests = [
+            Token::BigInt(BigInt::from(5)),
+            Token::BigInt(BigInt::from(i64::MAX) + 1),
+            Token::Plus,
+            Token::Int(1),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert!(lexer.get_errors().is_empty());
+    }
+    #[test]
+    fn test_big_integer_suffix_in_word() {
+        let mut lexer = Lexer::new("5nope + 1_0n2;");
+        assert_eq!(lexer.next_token(), Token::Illegal);
+        assert_eq!(lexer.next_token(), Token::Plus);
+        assert_eq!(lexer.next_token(), Token::Illegal);
+        assert_eq!(lexer.next_token(), Token::Semicolon);
+        assert_eq!(
+            lexer.get_errors(),
+            vec![
+                LexError::MalformedNumber {
+                    literal: String::from("5nope"),
+                    pos: pos(0, 1, 1),
+                },
+                LexError::MalformedNumber {
+                    literal: String::from("1_0n2"),
+                    pos: pos(8, 1, 9),
+                },
+            ]
+        );
+        assert_eq!(
+            lexer.get_errors()[0].to_string(),
+            "Malformed number literal 5nope at 1:1"
+        );
+    }
+    #[test]
+    fn test_float() {
+        let input = "3.25 1e-9 2.5E3 1e+2 10 5.method";
+        let tests = [
+            Token::Float(3.25),
+            Token::Float(1e-9),
+            Token::Float(2500.0),
+            Token::Float(100.0),
+            Token::Int(10),
+            Token::Int(5),
+            Token::Illegal,
+            Token::Ident("method".into()),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::IllegalChar {
+                ch: '.',
+                pos: pos(25, 1, 26),
+            }]
+        );
+    }
+    #[test]
+    fn test_float_overflow() {
+        let mut lexer = Lexer::new("1.7976931348623157e308 1e5000 1e-5000");
+        assert_eq!(lexer.next_token(), Token::Float(f64::MAX));
+        assert_eq!(lexer.next_token(), Token::Illegal);
+        assert_eq!(lexer.next_token(), Token::Float(0.0));
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::FloatOverflow {
+                literal: String::from("1e5000"),
+                pos: pos(23, 1, 24),
+            }]
+        );
+        assert_eq!(
+            lexer.get_errors()[0].to_string(),
+            "Float literal 1e5000 at 1:24 is out of range for a 64-bit float"
+        );
+    }
+    #[test]
+    fn test_radix_literals() {
+        let input =
+            "0xFF 0Xff 0b1010 0o755 1_000_000 0xFFFF_FFFF 0b1_0n 0x1_0000_0000_0000_0000n 0.5_5";
+        let tests = [
+            Token::Int(255),
+            Token::Int(255),
+            Token::Int(10),
+            Token::Int(493),
+            Token::Int(1_000_000),
+            Token::Int(0xFFFF_FFFF),
+            Token::BigInt(BigInt::from(2)),
+            Token::BigInt(BigInt::from(i64::MAX) * 2 + 2),
+            Token::Float(0.55),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert!(lexer.get_errors().is_empty());
+    }
+    #[test]
+    fn test_invalid_radix_literals() {
+        let input = "0b102; 0o78; 0xFG; 0x; 0x1_0000_0000_0000_0000;";
+        let mut lexer = Lexer::new(input);
+        let mut tok = lexer.next_token();
+        while tok != Token::Eof {
+            assert!(tok == Token::Illegal || tok == Token::Semicolon);
+            tok = lexer.next_token();
+        }
+        assert_eq!(
+            lexer.get_errors(),
+            vec![
+                LexError::InvalidDigit {
+                    literal: String::from("0b102"),
+                    digit: '2',
+                    radix: 2,
+                    pos: pos(4, 1, 5),
+                },
+                LexError::InvalidDigit {
+                    literal: String::from("0o78"),
+                    digit: '8',
+                    radix: 8,
+                    pos: pos(10, 1, 11),
+                },
+                LexError::InvalidDigit {
+                    literal: String::from("0xFG"),
+                    digit: 'G',
+                    radix: 16,
+                    pos: pos(16, 1, 17),
+                },
+                LexError::MalformedNumber {
+                    literal: String::from("0x"),
+                    pos: pos(19, 1, 20),
+                },
+                LexError::IntegerOverflow {
+                    literal: String::from("0x1_0000_0000_0000_0000"),
+                    pos: pos(23, 1, 24),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_comments() {
+        let input = r#"// leading comment
+        let x = 1; // trailing comment
+        /* block /* nested */ still a comment */ x / 2
+        /**/x"#;
+        let tests = [
+            Token::Let,
+            Token::Ident("x".into()),
+            Token::Assign,
+            Token::Int(1),
+            Token::Semicolon,
+            Token::Ident("x".into()),
+            Token::Slash,
+            Token::Int(2),
+            Token::Ident("x".into()),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert!(lexer.get_errors().is_empty());
+    }
+    #[test]
+    fn test_comment_tokens() {
+        let input = "x // line\n/* a /* b */ */ y";
+        let tests = [
+            Token::Ident("x".into()),
+            Token::Comment(Cow::from("// line")),
+            Token::Comment(Cow::from("/* a /* b */ */")),
+            Token::Ident("y".into()),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input).with_comments();
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+    }
+    #[test]
+    fn test_unterminated_comment() {
+        let mut lexer = Lexer::new("1 /* a /* b */");
+        assert_eq!(lexer.next_token(), Token::Int(1));
+        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::UnterminatedComment { pos: pos(2, 1, 3) }]
+        );
+    }
+    #[test]
+    fn test_unicode() {
+        let input = "let café = naïve_1 + 变量; 🐒 é";
+        let tests = [
+            Token::Let,
+            Token::Ident("café".into()),
+            Token::Assign,
+            Token::Ident("naïve_1".into()),
+            Token::Plus,
+            Token::Ident("变量".into()),
+            Token::Semicolon,
+            Token::Illegal,
+            Token::Ident("é".into()),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(input);
+        for res in tests {
+            let curr = lexer.next_token();
+            assert_eq!(res, curr);
+        }
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::IllegalChar {
+                ch: '🐒',
+                pos: pos(31, 1, 26),
+            }]
+        );
+        assert_eq!(
+            lexer.get_errors()[0].to_string(),
+            "Illegal character '🐒' at 1:26"
+        );
+    }
+    #[test]
+    fn test_error_positions_count_chars() {
+        let input = "/* ünïcödé */ let é = 1;\n  0b2";
+        let mut lexer = Lexer::new(input);
+        while lexer.next_token() != Token::Eof {}
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::InvalidDigit {
+                literal: String::from("0b2"),
+                digit: '2',
+                radix: 2,
+                pos: pos(34, 2, 5),
+            }]
+        );
+        let mut lexer = Lexer::new("é 99999999999999999999");
+        lexer.next_token();
+        lexer.next_token();
+        assert_eq!(
+            lexer.take_errors()[0].to_string(),
+            "Integer literal 99999999999999999999 at 1:3 is out of range for a 64-bit integer, use 99999999999999999999n for a big integer"
+        );
+    }
+    #[test]
+    fn test_iterator() {
+        let tokens: Vec<SpannedToken> = Lexer::new("let é =\n 10;").with_file(FileId(3)).collect();
+        let span = |start: (usize, usize, usize), end: (usize, usize, usize)| Span {
+            file: FileId(3),
+            start: pos(start.0, start.1, start.2),
+            end: pos(end.0, end.1, end.2),
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                SpannedToken {
+                    token: Token::Let,
+                    span: span((0, 1, 1), (3, 1, 4)),
+                },
+                SpannedToken {
+                    token: Token::Ident("é".into()),
+                    span: span((4, 1, 5), (6, 1, 6)),
+                },
+                SpannedToken {
+                    token: Token::Assign,
+                    span: span((7, 1, 7), (8, 1, 8)),
+                },
+                SpannedToken {
+                    token: Token::Int(10),
+                    span: span((10, 2, 2), (12, 2, 4)),
+                },
+                SpannedToken {
+                    token: Token::Semicolon,
+                    span: span((12, 2, 4), (13, 2, 5)),
+                },
+                SpannedToken {
+                    token: Token::Eof,
+                    span: span((13, 2, 5), (13, 2, 5)),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("x + 1").unwrap();
+        assert_eq!(
+            tokens.into_iter().map(|tok| tok.token).collect::<Vec<_>>(),
+            vec![
+                Token::Ident("x".into()),
+                Token::Plus,
+                Token::Int(1),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokenize("1 + 0b2"),
+            Err(LexError::InvalidDigit {
+                literal: String::from("0b2"),
+                digit: '2',
+                radix: 2,
+                pos: pos(6, 1, 7),
+            })
+        );
+    }
+    #[test]
+    fn test_malformed_exponent() {
+        let mut lexer = Lexer::new("1e+;");
+        assert_eq!(lexer.next_token(), Token::Illegal);
+        assert_eq!(lexer.next_token(), Token::Semicolon);
+        assert_eq!(
+            lexer.get_errors(),
+            vec![LexError::MalformedNumber {
+                literal: String::from("1e+"),
+                pos: pos(0, 1, 1),
+            }]
+        );
+    }
 }
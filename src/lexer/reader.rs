@@ -0,0 +1,206 @@
+use std::io::{self, Read};
+
+use super::{LexError, Lexer, Trivia};
+use crate::token::{Position, SpannedToken, Token};
+
+// Bytes read from the underlying reader at a time, more when a single token is longer
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Lexes a source read incrementally from any `io::Read`, so the whole input never has to be
+// loaded at once. Only the text from the start of the current token on is kept in memory,
+// whitespace and comments are skipped chunk by chunk. Tokens own their text for that reason.
+pub struct ReaderLexer<R: Read> {
+    reader: R,
+    chunk_size: usize,
+    buf: String,
+    // Bytes of `buf` already lexed, `buf[consumed..]` starts at `start`
+    consumed: usize,
+    start: Position,
+    // Trailing bytes of a char split between two reads
+    partial_char: Vec<u8>,
+    // Comment the last chunk ended in
+    trivia: Trivia,
+    reader_done: bool,
+    finished: bool,
+    errors: Vec<LexError>,
+}
+
+impl<R: Read> ReaderLexer<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderLexer {
+            reader,
+            chunk_size: CHUNK_SIZE,
+            buf: String::new(),
+            consumed: 0,
+            start: Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+            partial_char: vec![],
+            trivia: Trivia::Space,
+            reader_done: false,
+            finished: false,
+            errors: vec![],
+        }
+    }
+
+    // Small chunks make tests split tokens, comments and chars between reads
+    #[cfg(test)]
+    fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn next_spanned_token(&mut self) -> io::Result<SpannedToken<'static>> {
+        loop {
+            let mut lexer = Lexer::resume(&self.buf[self.consumed..], self.start);
+            let trivia = lexer.skip_trivia(self.trivia, !self.reader_done);
+            self.errors.extend(lexer.take_errors());
+            self.consumed += lexer.pos;
+            self.start = lexer.curr_position();
+            match trivia {
+                Some(trivia) => {
+                    self.trivia = trivia;
+                    self.fill()?;
+                }
+                None => {
+                    self.trivia = Trivia::Space;
+                    break;
+                }
+            }
+        }
+        loop {
+            let mut lexer = Lexer::resume(&self.buf[self.consumed..], self.start);
+            let token = lexer.next_spanned_token();
+            // The token could continue past the text read so far, read more and lex it again
+            if lexer.next_pos >= lexer.input.len() && !self.reader_done {
+                self.fill()?;
+                continue;
+            }
+            self.errors.extend(lexer.take_errors());
+            self.consumed += lexer.pos;
+            self.start = token.span.end;
+            return Ok(token.into_owned());
+        }
+    }
+
+    // Appends the next chunk of the reader to `buf`, at least as long as the text not lexed yet
+    // so relexing a long token stays linear overall
+    fn fill(&mut self) -> io::Result<()> {
+        // Drop the lexed text once it is most of the buffer, so moving the rest is amortized
+        if self.consumed > self.buf.len() / 2 {
+            self.buf.drain(..self.consumed);
+            self.consumed = 0;
+        }
+        let want = self.chunk_size.max(self.buf.len() - self.consumed);
+        let mut bytes = std::mem::take(&mut self.partial_char);
+        let read = self
+            .reader
+            .by_ref()
+            .take(want as u64)
+            .read_to_end(&mut bytes)?;
+        if read == 0 {
+            self.reader_done = true;
+        }
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            // Only the end of a char that is split between reads may be missing
+            Err(err) if err.error_len().is_none() && !self.reader_done => err.valid_up_to(),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                ))
+            }
+        };
+        self.partial_char = bytes.split_off(valid);
+        self.buf.push_str(std::str::from_utf8(&bytes).unwrap());
+        Ok(())
+    }
+
+    pub fn get_errors(&self) -> Vec<LexError> {
+        self.errors.clone()
+    }
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+// Same as the `Lexer` iterator, except reading the source can fail
+impl<R: Read> Iterator for ReaderLexer<R> {
+    type Item = io::Result<SpannedToken<'static>>;
+
+    fn next(&mut self) -> Option<io::Result<SpannedToken<'static>>> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_spanned_token();
+        self.finished = match token {
+            Ok(ref token) => token.token == Token::Eof,
+            Err(_) => true,
+        };
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out the input a few bytes at a time to exercise refilling in the middle of tokens
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.0.len().min(buf.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_matches_str_lexer() {
+        let input = r#"let café = fn(x, y) {
+            /* a comment
+               over /* nested */ lines */
+            x + y; // trailing
+        };
+        let result = add(0xFF_FF, 12345678901234567890n) == 2.5e10;
+        let worse = 5nope + 5n;
+        let bad = 0b12 /* unterminated"#;
+        assert_matches_str_lexer(input, 3);
+    }
+    #[test]
+    fn test_long_tokens_and_comments() {
+        let comment = format!("/* {} */", "a /* b */ c\n".repeat(5000));
+        let line = format!("let x = {}1;", "1 + ".repeat(5000));
+        let ident = "é".repeat(5000);
+        let input = format!("{comment}{line}\n// {line}\n{ident} = 1;\n/* {comment}");
+        assert_matches_str_lexer(&input, 1);
+    }
+
+    fn assert_matches_str_lexer(input: &str, errors: usize) {
+        let mut lexer = Lexer::new(input);
+        let expected: Vec<SpannedToken> = lexer.by_ref().collect();
+        for chunk_size in [1, 2, 3, 7, 64] {
+            let mut reader =
+                ReaderLexer::new(Trickle(input.as_bytes())).with_chunk_size(chunk_size);
+            let tokens: Vec<SpannedToken> = reader.by_ref().map(Result::unwrap).collect();
+            assert_eq!(tokens, expected, "chunk size {}", chunk_size);
+            assert_eq!(reader.get_errors(), lexer.get_errors());
+        }
+        assert_eq!(lexer.get_errors().len(), errors);
+    }
+    #[test]
+    fn test_invalid_utf8() {
+        let mut reader = ReaderLexer::new(&b"let x = \xFF;"[..]);
+        let err = reader.find_map(Result::err).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+        // A char cut off by the end of the stream
+        let mut reader = ReaderLexer::new(&"let é".as_bytes()[..5]).with_chunk_size(1);
+        let err = reader.find_map(Result::err).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
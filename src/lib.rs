@@ -0,0 +1,14 @@
+pub mod bigint;
+pub mod builtins;
+pub mod cst;
+pub mod diagnostics;
+pub mod formatter;
+pub mod lexer;
+pub mod lint;
+pub mod optimize;
+pub mod parser;
+pub mod resolver;
+pub mod suggest;
+pub mod symbol;
+pub mod token;
+pub mod types;
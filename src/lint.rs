@@ -0,0 +1,478 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::builtins::Builtin;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::printer::Parens;
+use crate::parser::visit::{walk_expr, walk_program, walk_stmt, Visitor};
+use crate::parser::{ParseErrors, Parser};
+use crate::resolver::{resolve, Resolution, ResolveError};
+use crate::token::{Span, Token};
+use crate::types::{check, Type, TypeCheck};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Lint {
+    UnusedLet,
+    UnreachableCode,
+    ConstantCondition,
+    SelfComparison,
+    ShadowedBuiltin,
+}
+
+impl Lint {
+    pub const ALL: &'static [Lint] = &[
+        Lint::UnusedLet,
+        Lint::UnreachableCode,
+        Lint::ConstantCondition,
+        Lint::SelfComparison,
+        Lint::ShadowedBuiltin,
+    ];
+
+    // Name used in config files, inline comments and diagnostics
+    pub fn id(self) -> &'static str {
+        match self {
+            Lint::UnusedLet => "unused-let",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::SelfComparison => "self-comparison",
+            Lint::ShadowedBuiltin => "shadowed-builtin",
+        }
+    }
+    pub fn from_id(id: &str) -> Option<Lint> {
+        Lint::ALL.iter().copied().find(|lint| lint.id() == id)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+// Level of every lint, each one warns unless configured otherwise
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+}
+
+impl LintConfig {
+    // Reads `lint-id = allow|warn|deny` lines, `#` starts a comment:
+    //
+    // # tests bind values only to look at them in a debugger
+    // unused-let = allow
+    // self-comparison = deny
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = LintConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((id, level)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `lint = level`", i + 1));
+            };
+            let lint = Lint::from_id(id.trim())
+                .ok_or_else(|| format!("line {}: unknown lint `{}`", i + 1, id.trim()))?;
+            let level = Level::from_name(level.trim()).ok_or_else(|| {
+                format!(
+                    "line {}: expected allow, warn or deny, got `{}`",
+                    i + 1,
+                    level.trim()
+                )
+            })?;
+            config.set(lint, level);
+        }
+        Ok(config)
+    }
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+// Parses `input` and runs every lint not allowed by `config` or by an inline comment, in
+// source order, along with errors for undeclared names. `// lint: allow(unused-let, ...)`
+// silences the lints on its own line, and on the line after it when the comment is alone on its
+// line. `warn` and `deny` raise them the same way.
+pub fn lint_source(input: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, ParseErrors> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let directives = inline_directives(input);
+
+    let resolution = resolve(&program);
+    let types = check(&program, &resolution);
+
+    let mut checker = Checker {
+        types: &types,
+        found: vec![],
+    };
+    walk_program(&mut checker, &program);
+    let mut found = checker.found;
+    found.extend(unused_lets(&program, &resolution));
+
+    let mut diagnostics: Vec<Diagnostic> = found
+        .into_iter()
+        .filter_map(|(lint, diagnostic)| {
+            let line = diagnostic.span.start.line;
+            let level = directives
+                .get(&(line, lint))
+                .copied()
+                .unwrap_or_else(|| config.level(lint));
+            let severity = match level {
+                Level::Allow => return None,
+                Level::Warn => Severity::Warning,
+                Level::Deny => Severity::Error,
+            };
+            Some(Diagnostic {
+                severity,
+                ..diagnostic.with_code(lint.id())
+            })
+        })
+        .collect();
+    diagnostics.extend(resolution.errors.iter().map(ResolveError::to_diagnostic));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+    Ok(diagnostics)
+}
+
+// Levels set by `// lint: level(ids)` comments, keyed by the lines they apply to
+fn inline_directives(input: &str) -> HashMap<(usize, Lint), Level> {
+    let mut directives = HashMap::new();
+    for token in Lexer::new(input).with_comments() {
+        let Token::Comment(text) = &token.token else {
+            continue;
+        };
+        let Some(directive) = text.strip_prefix("//").map(str::trim) else {
+            continue;
+        };
+        let Some(directive) = directive.strip_prefix("lint:").map(str::trim) else {
+            continue;
+        };
+        let Some((level, ids)) = directive.split_once('(') else {
+            continue;
+        };
+        let Some(level) = Level::from_name(level.trim()) else {
+            continue;
+        };
+        let ids = ids.split(')').next().unwrap_or("");
+        let line = token.span.start.line;
+        // A comment after code only covers that code
+        let start = token.span.start.offset;
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let own_line = input[line_start..start].trim().is_empty();
+        for lint in ids.split(',').filter_map(|id| Lint::from_id(id.trim())) {
+            directives.insert((line, lint), level);
+            if own_line {
+                directives.insert((line + 1, lint), level);
+            }
+        }
+    }
+    directives
+}
+
+// `let`s no identifier resolves to, names starting with `_` are meant to be unused
+fn unused_lets(program: &Program, resolution: &Resolution) -> Vec<(Lint, Diagnostic)> {
+    let used: HashSet<StmtId> = resolution.definitions.values().flatten().copied().collect();
+    resolution
+        .declarations
+        .keys()
+        .filter(|id| !used.contains(id))
+        .filter_map(|id| match &program[*id] {
+            Statement::Let(Ident(name), _, _) if !program.name(*name).starts_with('_') => {
+                let name = program.name(*name);
+                let diagnostic =
+                    Diagnostic::warning(format!("`{}` is never used", name), program.span(*id))
+                        .with_help(format!("remove the binding or rename it to `_{}`", name));
+                Some((Lint::UnusedLet, diagnostic))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// The lints that look at the shape of the tree, `types` only refines what they say
+struct Checker<'a> {
+    types: &'a TypeCheck,
+    found: Vec<(Lint, Diagnostic)>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, lint: Lint, message: String, span: Span, help: &str) {
+        let diagnostic = Diagnostic::warning(message, span).with_help(help);
+        self.found.push((lint, diagnostic));
+    }
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_block(&mut self, program: &Program, block: &[StmtId]) {
+        let returns = block
+            .iter()
+            .position(|id| matches!(program[*id], Statement::Return(_)));
+        if let Some(after) = returns.and_then(|i| block.get(i + 1..)) {
+            let reachable = after
+                .iter()
+                .filter(|id| program[**id] != Statement::Blank)
+                .collect::<Vec<_>>();
+            if let (Some(first), Some(last)) = (reachable.first(), reachable.last()) {
+                let span = Span {
+                    start: program.span(**first).start,
+                    end: program.span(**last).end,
+                    ..Span::default()
+                };
+                self.report(
+                    Lint::UnreachableCode,
+                    "unreachable statement after `return`".to_string(),
+                    span,
+                    "remove it or move it before the `return`",
+                );
+            }
+        }
+        for id in block {
+            self.visit_stmt(program, *id);
+        }
+    }
+    fn visit_stmt(&mut self, program: &Program, id: StmtId) {
+        if let Statement::Let(name, _, _) = &program[id] {
+            let name = program.name(name.0);
+            if Builtin::from_name(name).is_some() {
+                self.report(
+                    Lint::ShadowedBuiltin,
+                    format!("`{}` shadows the builtin of the same name", name),
+                    program.span(id),
+                    "pick another name to keep the builtin reachable",
+                );
+            }
+        }
+        walk_stmt(self, program, id)
+    }
+    fn visit_expr(&mut self, program: &Program, id: ExprId) {
+        match &program[id] {
+            Expr::If { cond, .. } => {
+                if let Expr::Literal(literal) = &program[*cond] {
+                    let message = match literal {
+                        Literal::Boolean(true) => "this `if` always takes its first branch",
+                        Literal::Boolean(false) => "this `if` never takes its first branch",
+                        _ => "the condition of this `if` is a constant",
+                    };
+                    self.report(
+                        Lint::ConstantCondition,
+                        message.to_string(),
+                        program.span(*cond),
+                        "replace the `if` with the branch that runs",
+                    );
+                }
+            }
+            Expr::Infix(left, infix, right)
+                if matches!(
+                    infix,
+                    Infix::Equal
+                        | Infix::NotEqual
+                        | Infix::LessThan
+                        | Infix::LessThanEqual
+                        | Infix::GreaterThan
+                        | Infix::GreaterThanEqual
+                ) && program.expr_eq(*left, program, *right) =>
+            {
+                // A NaN float is the one value unequal to itself
+                let float = !matches!(
+                    self.types.get(*left),
+                    Some(Type::Int | Type::BigInt | Type::Bool | Type::String | Type::Null)
+                );
+                let always = match infix {
+                    Infix::LessThan | Infix::GreaterThan => "this is always false",
+                    Infix::Equal | Infix::LessThanEqual | Infix::GreaterThanEqual if float => {
+                        "this is true unless the value is NaN"
+                    }
+                    Infix::NotEqual if float => "this is false unless the value is NaN",
+                    Infix::NotEqual => "this is always false",
+                    _ => "this is always true",
+                };
+                self.report(
+                    Lint::SelfComparison,
+                    format!(
+                        "comparing `{}` with itself",
+                        program.display_node(*left, Parens::Minimal)
+                    ),
+                    program.span(id),
+                    always,
+                );
+            }
+            _ => {}
+        }
+        walk_expr(self, program, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(input: &str, config: &LintConfig) -> Vec<(String, Severity, usize)> {
+        lint_source(input, config)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.code.unwrap(), d.severity, d.span.start.line))
+            .collect()
+    }
+    fn warning(id: &str, line: usize) -> (String, Severity, usize) {
+        (id.to_string(), Severity::Warning, line)
+    }
+
+    #[test]
+    fn test_lints() {
+        let input = "let a = 1;
+let _b = 2;
+let len = 3;
+if (true) { a }
+if (a == a) { return a; a + 1; len } else { 5 > 5 }
+let c = len;
+return c;
+c;";
+        assert_eq!(
+            lint(input, &LintConfig::default()),
+            vec![
+                warning("shadowed-builtin", 3),
+                warning("constant-condition", 4),
+                warning("self-comparison", 5),
+                warning("unreachable-code", 5),
+                warning("self-comparison", 5),
+                warning("unreachable-code", 8),
+            ]
+        );
+    }
+    #[test]
+    fn test_unused_let() {
+        let input = "let a = 1;\nlet b = a;\nif (b) { let a = 2; let c = a; }";
+        let diagnostics = lint_source(input, &LintConfig::default()).unwrap();
+        let messages: Vec<(String, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.message.clone(), d.span.start.line))
+            .collect();
+        assert_eq!(messages, vec![("`c` is never used".to_string(), 3)]);
+        assert_eq!(
+            diagnostics[0].render(input, "main.mk"),
+            "warning[unused-let]: `c` is never used
+ --> main.mk:3:21
+  |
+3 | if (b) { let a = 2; let c = a; }
+  |                     ^^^^^^^^^^
+  = help: remove the binding or rename it to `_c`"
+        );
+    }
+    #[test]
+    fn test_config() {
+        let config = LintConfig::parse(
+            "# comment\nunused-let = allow\n\nself-comparison = deny # no x == x\n",
+        )
+        .unwrap();
+        assert_eq!(config.level(Lint::UnusedLet), Level::Allow);
+        assert_eq!(config.level(Lint::SelfComparison), Level::Deny);
+        assert_eq!(config.level(Lint::ShadowedBuiltin), Level::Warn);
+        assert_eq!(
+            lint("let x = 1;\nx == x", &config),
+            vec![("self-comparison".to_string(), Severity::Error, 2)]
+        );
+
+        assert_eq!(
+            LintConfig::parse("unused = allow"),
+            Err("line 1: unknown lint `unused`".to_string())
+        );
+        assert_eq!(
+            LintConfig::parse("\nunused-let allow"),
+            Err("line 2: expected `lint = level`".to_string())
+        );
+        assert_eq!(
+            LintConfig::parse("unused-let = off"),
+            Err("line 1: expected allow, warn or deny, got `off`".to_string())
+        );
+    }
+    #[test]
+    fn test_self_comparison_help() {
+        let input = "let a = 1; let f = 2.5; a == a; a != a; f == f; f != f; f < f; g == g;";
+        let help: Vec<String> = lint_source(input, &LintConfig::default())
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.code.as_deref() == Some("self-comparison"))
+            .map(|d| d.help.unwrap())
+            .collect();
+        assert_eq!(
+            help,
+            vec![
+                "this is always true",
+                "this is always false",
+                "this is true unless the value is NaN",
+                "this is false unless the value is NaN",
+                "this is always false",
+                // Nothing says `g` is not a float
+                "this is true unless the value is NaN",
+            ]
+        );
+    }
+    #[test]
+    fn test_inline_directives() {
+        let input = "// lint: allow(unused-let, shadowed-builtin)
+let first = 1;
+let a = 1; // lint: deny(unused-let)
+let b = 2;
+let c = 3;";
+        assert_eq!(
+            lint(input, &LintConfig::default()),
+            vec![
+                ("unused-let".to_string(), Severity::Error, 3),
+                warning("unused-let", 4),
+                warning("unused-let", 5),
+            ]
+        );
+    }
+    #[test]
+    fn test_undeclared() {
+        let input = "let count = 1;\nretrun cout + count;";
+        let diagnostics = lint_source(input, &LintConfig::default()).unwrap();
+        let rendered: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(input, "main.mk"))
+            .collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "error: `retrun` is not declared
+ --> main.mk:2:1
+  |
+2 | retrun cout + count;
+  | ^^^^^^
+  = help: did you mean `return`?",
+                "error: `cout` is not declared
+ --> main.mk:2:8
+  |
+2 | retrun cout + count;
+  |        ^^^^
+  = help: did you mean `count`?",
+            ]
+        );
+    }
+}
@@ -1,15 +1,48 @@
-use std::io::{self};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
 
-use lexer::Lexer;
-use token::Token;
+use monkey_lang::diagnostics::Severity;
+use monkey_lang::formatter::{format_source, FormatConfig};
+use monkey_lang::lexer::Lexer;
+use monkey_lang::lint::{lint_source, LintConfig};
+use monkey_lang::parser::Parser;
+use monkey_lang::token::{SpannedToken, Token};
+use monkey_lang::types::check_source;
 
-mod lexer;
-mod parser;
-mod token;
+const USAGE: &str = "usage: monkey [command]
+
+Commands:
+    (none)                              start the REPL
+    fmt [--check] [--max-width N] [files...]
+                                        format files in place, or stdin to stdout
+    ast [--format sexpr|json] [file]    print the syntax tree of a file or stdin
+    tokens [--format text|json] [file]  print the tokens of a file or stdin
+    lint [--config path] [files...]     report suspicious code in files or stdin, lint
+                                        levels are read from .monkeylint by default
+    check [files...]                    report type errors in files or stdin
+
+JSON output needs monkey to be built with `--features serde`.";
 
 fn main() {
-    println!("Hello this is the Monkey programming language!\n");
-    repl()
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => {
+            println!("Hello this is the Monkey programming language!\n");
+            repl()
+        }
+        Some("fmt") => process::exit(fmt(&args[1..])),
+        Some("ast") => process::exit(ast(&args[1..])),
+        Some("tokens") => process::exit(tokens(&args[1..])),
+        Some("lint") => process::exit(lint(&args[1..])),
+        Some("check") => process::exit(check(&args[1..])),
+        Some("-h" | "--help" | "help") => println!("{}", USAGE),
+        Some(command) => {
+            eprintln!("unknown command `{}`\n{}", command, USAGE);
+            process::exit(2)
+        }
+    }
 }
 
 fn repl() {
@@ -22,5 +55,307 @@ fn repl() {
             println!("{:?}", tok);
             tok = lexer.next_token();
         }
+        for err in lexer.take_errors() {
+            eprintln!("lexer error: {}", err);
+        }
+    }
+}
+
+// Exits with 1 when a file fails to parse or, with `--check`, would be reformatted
+fn fmt(args: &[String]) -> i32 {
+    let mut config = FormatConfig::default();
+    let mut check = false;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--max-width" => match args.next().and_then(|width| width.parse().ok()) {
+                Some(width) => config.max_width = width,
+                None => {
+                    eprintln!("--max-width expects a number\n{}", USAGE);
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("unknown option `{}` for fmt\n{}", flag, USAGE);
+                return 2;
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    if files.is_empty() {
+        let mut input = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut input) {
+            eprintln!("<stdin>: {}", err);
+            return 1;
+        }
+        return match format_source(&input, &config) {
+            Ok(formatted) if check => (formatted != input) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(errors) => {
+                report_parse_errors("<stdin>", &errors);
+                1
+            }
+        };
+    }
+
+    let mut status = 0;
+    for file in &files {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(&input, &config) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                report_parse_errors(file, &errors);
+                status = 1;
+                continue;
+            }
+        };
+        if formatted == input {
+            continue;
+        }
+        if check {
+            println!("would reformat {}", file);
+            status = 1;
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("{}: {}", file, err);
+            status = 1;
+        }
+    }
+    status
+}
+
+fn report_parse_errors(file: &str, errors: &[String]) {
+    for err in errors {
+        eprintln!("{}: {}", file, err);
+    }
+}
+
+// Splits `[--format F] [file]`, the format defaults to the first of `formats`
+fn format_args<'a>(
+    args: &'a [String],
+    formats: &[&'a str],
+) -> Result<(&'a str, Option<&'a str>), i32> {
+    let mut format = formats[0];
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next() {
+                Some(name) if formats.contains(&name.as_str()) => format = name,
+                _ => {
+                    eprintln!("--format expects one of {}\n{}", formats.join(", "), USAGE);
+                    return Err(2);
+                }
+            },
+            _ if file.is_none() => file = Some(arg.as_str()),
+            _ => {
+                eprintln!("unexpected argument `{}`\n{}", arg, USAGE);
+                return Err(2);
+            }
+        }
+    }
+    Ok((format, file))
+}
+// Contents of `file`, or of stdin when there is none
+fn read_input(file: Option<&str>) -> Result<String, i32> {
+    let result = match file {
+        Some(file) => fs::read_to_string(file),
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map(|_| input)
+        }
+    };
+    result.map_err(|err| {
+        eprintln!("{}: {}", file.unwrap_or("<stdin>"), err);
+        1
+    })
+}
+
+fn ast(args: &[String]) -> i32 {
+    let (format, file) = match format_args(args, &["sexpr", "json"]) {
+        Ok(args) => args,
+        Err(status) => return status,
+    };
+    let input = match read_input(file) {
+        Ok(input) => input,
+        Err(status) => return status,
+    };
+    let mut parser = Parser::new(Lexer::new(&input));
+    let program = parser.parse_program();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        report_parse_errors(file.unwrap_or("<stdin>"), &errors);
+        return 1;
+    }
+    match format {
+        "json" => print_json(&program),
+        _ => {
+            println!("{}", program.to_sexpr());
+            0
+        }
+    }
+}
+
+fn tokens(args: &[String]) -> i32 {
+    let (format, file) = match format_args(args, &["text", "json"]) {
+        Ok(args) => args,
+        Err(status) => return status,
+    };
+    let input = match read_input(file) {
+        Ok(input) => input,
+        Err(status) => return status,
+    };
+    let mut lexer = Lexer::new(&input).with_comments();
+    let tokens: Vec<SpannedToken> = lexer.by_ref().collect();
+    let status = match format {
+        "json" => print_json(&tokens),
+        _ => {
+            for token in &tokens {
+                println!("{}-{} {:?}", token.span.start, token.span.end, token.token);
+            }
+            0
+        }
+    };
+    for err in lexer.take_errors() {
+        eprintln!("{}: {}", file.unwrap_or("<stdin>"), err);
+    }
+    status
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T: serde::Serialize>(value: &T) -> i32 {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(err) => {
+            eprintln!("cannot write JSON: {}", err);
+            1
+        }
+    }
+}
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_value: &T) -> i32 {
+    eprintln!("JSON output needs monkey to be built with the `serde` feature");
+    2
+}
+
+// Default config, read from the current directory when `--config` is not given
+const LINT_CONFIG: &str = ".monkeylint";
+
+// Exits with 1 when a file fails to parse or a denied lint fires
+fn lint(args: &[String]) -> i32 {
+    let mut config_file = None;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => match args.next() {
+                Some(path) => config_file = Some(path.as_str()),
+                None => {
+                    eprintln!("--config expects a path\n{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => files.push(Some(arg.as_str())),
+        }
+    }
+    if files.is_empty() {
+        files.push(None);
+    }
+
+    let config = match lint_config(config_file) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+
+    let mut status = 0;
+    for file in files {
+        let input = match read_input(file) {
+            Ok(input) => input,
+            Err(code) => {
+                status = code;
+                continue;
+            }
+        };
+        let name = file.unwrap_or("<stdin>");
+        match lint_source(&input, &config) {
+            Ok(diagnostics) => {
+                for diagnostic in diagnostics {
+                    eprintln!("{}\n", diagnostic.render(&input, name));
+                    if diagnostic.severity == Severity::Error {
+                        status = 1;
+                    }
+                }
+            }
+            Err(errors) => {
+                report_parse_errors(name, &errors);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+// Reads `path`, or `.monkeylint` if it exists, all lints warn without a config
+fn lint_config(path: Option<&str>) -> Result<LintConfig, String> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path),
+        None => match fs::read_to_string(LINT_CONFIG) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(LintConfig::default()),
+            result => result,
+        },
+    };
+    let path = path.unwrap_or(LINT_CONFIG);
+    let text = text.map_err(|err| format!("{}: {}", path, err))?;
+    LintConfig::parse(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+// Exits with 1 when a file fails to parse or has a type error
+fn check(args: &[String]) -> i32 {
+    let mut files: Vec<Option<&str>> = args.iter().map(|arg| Some(arg.as_str())).collect();
+    if files.is_empty() {
+        files.push(None);
+    }
+    let mut status = 0;
+    for file in files {
+        let input = match read_input(file) {
+            Ok(input) => input,
+            Err(code) => {
+                status = code;
+                continue;
+            }
+        };
+        let name = file.unwrap_or("<stdin>");
+        match check_source(&input) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    eprintln!("{}\n", diagnostic.render(&input, name));
+                }
+                if !diagnostics.is_empty() {
+                    status = 1;
+                }
+            }
+            Err(errors) => {
+                report_parse_errors(name, &errors);
+                status = 1;
+            }
+        }
     }
+    status
 }
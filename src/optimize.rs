@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::parser::ast::*;
+use crate::parser::visit::{fold_expr_children, fold_program, fold_stmt_children, Fold};
+use crate::resolver::resolve;
+
+// Folds operators applied to literals, drops the branches of `if`s whose condition is a boolean
+// literal, and inlines `let`s bound to a literal. Anything that could fail at runtime, like
+// `1 / 0` or an overflowing `+`, is left in place so it still fails the same way.
+pub fn optimize(program: Program) -> Program {
+    let resolution = resolve(&program);
+    // Only a use a single `let` reaches can take its value
+    let definitions = resolution
+        .definitions
+        .into_iter()
+        .filter_map(|(id, definitions)| match definitions[..] {
+            [definition] => Some((id, definition)),
+            _ => None,
+        })
+        .collect();
+    let mut optimizer = Optimizer {
+        definitions,
+        constants: HashMap::new(),
+    };
+    fold_program(&mut optimizer, program)
+}
+
+struct Optimizer {
+    // The `let` each identifier refers to when only one can, keyed by the ids of the
+    // unoptimized program
+    definitions: HashMap<ExprId, StmtId>,
+    // Values of the `let`s that folded down to a literal, while the block declaring them is open
+    constants: HashMap<StmtId, Literal>,
+}
+
+impl Fold for Optimizer {
+    fn fold_block(&mut self, program: &mut Program, block: BlockStatement) -> BlockStatement {
+        let len = block.len();
+        let mut folded = vec![];
+        // Only uses in this block and the ones inside it are sure the `let`s here ran
+        let declared = block.clone();
+        for (i, id) in block.into_iter().enumerate() {
+            let Some(id) = self.fold_stmt(program, id) else {
+                continue;
+            };
+            // The last statement gives the block its value, an `if` running nothing gives null
+            let last = i + 1 == len;
+            match taken_branch(program, id) {
+                // The branch already runs in this block's scope
+                Some(Some(branch)) if !(last && branch.is_empty()) => folded.extend(branch),
+                Some(None) if !last => {}
+                _ => folded.push(id),
+            }
+        }
+        for id in declared {
+            self.constants.remove(&id);
+        }
+        folded
+    }
+    fn fold_stmt(&mut self, program: &mut Program, id: StmtId) -> Option<StmtId> {
+        let folded = fold_stmt_children(self, program, id);
+        if let Statement::Let(_, _, value) = &program[folded] {
+            if let Expr::Literal(literal) = &program[*value] {
+                self.constants.insert(id, literal.clone());
+            }
+        }
+        Some(folded)
+    }
+    fn fold_expr(&mut self, program: &mut Program, id: ExprId) -> ExprId {
+        let constant = self
+            .definitions
+            .get(&id)
+            .and_then(|definition| self.constants.get(definition));
+        if let Some(literal) = constant {
+            let span = program.span(id);
+            return program.alloc_expr(Expr::Literal(literal.clone()), span);
+        }
+        let id = fold_expr_children(self, program, id);
+        let literal = match &program[id] {
+            Expr::Prefix(prefix, right) => match &program[*right] {
+                Expr::Literal(right) => fold_prefix(prefix, right),
+                _ => None,
+            },
+            Expr::Infix(left, infix, right) => match (&program[*left], &program[*right]) {
+                (Expr::Literal(left), Expr::Literal(right)) => fold_infix(left, infix, right),
+                _ => None,
+            },
+            // Only an `if` whose branch is a single expression can become that expression
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                let branch = match program[*cond] {
+                    Expr::Literal(Literal::Boolean(true)) => Some(consequence),
+                    Expr::Literal(Literal::Boolean(false)) => alternative.as_ref(),
+                    _ => None,
+                };
+                if let Some([stmt]) = branch.map(Vec::as_slice) {
+                    if let Statement::Expr(value) = program[*stmt] {
+                        return value;
+                    }
+                }
+                None
+            }
+            Expr::Ident(_) | Expr::Literal(_) => None,
+        };
+        match literal {
+            Some(literal) => {
+                let span = program.span(id);
+                program.alloc_expr(Expr::Literal(literal), span)
+            }
+            None => id,
+        }
+    }
+}
+
+// For an `if` statement with a boolean literal condition, the statements that run: `None`
+// when the condition is false and there is no `else`
+fn taken_branch(program: &Program, id: StmtId) -> Option<Option<BlockStatement>> {
+    let Statement::Expr(value) = program[id] else {
+        return None;
+    };
+    let Expr::If {
+        cond,
+        consequence,
+        alternative,
+    } = &program[value]
+    else {
+        return None;
+    };
+    match program[*cond] {
+        Expr::Literal(Literal::Boolean(true)) => Some(Some(consequence.clone())),
+        Expr::Literal(Literal::Boolean(false)) => Some(alternative.clone()),
+        _ => None,
+    }
+}
+
+fn fold_prefix(prefix: &Prefix, right: &Literal) -> Option<Literal> {
+    let literal = match (prefix, right) {
+        (Prefix::Minus, Literal::Int(int)) => Literal::Int(int.checked_neg()?),
+        (Prefix::Minus, Literal::BigInt(int)) => Literal::BigInt(-int.clone()),
+        (Prefix::Minus, Literal::Float(float)) => Literal::Float(-float),
+        (Prefix::Not, Literal::Boolean(boolean)) => Literal::Boolean(!boolean),
+        _ => return None,
+    };
+    Some(literal)
+}
+
+// Operands of different types are left alone, as is anything that would fail at runtime
+fn fold_infix(left: &Literal, infix: &Infix, right: &Literal) -> Option<Literal> {
+    let literal = match (left, right) {
+        (Literal::Int(left), Literal::Int(right)) => match infix {
+            Infix::Plus => Literal::Int(left.checked_add(*right)?),
+            Infix::Minus => Literal::Int(left.checked_sub(*right)?),
+            Infix::Multiply => Literal::Int(left.checked_mul(*right)?),
+            Infix::Divide => Literal::Int(left.checked_div(*right)?),
+            _ => Literal::Boolean(compare(infix, left.cmp(right))?),
+        },
+        (Literal::BigInt(left), Literal::BigInt(right)) => match infix {
+            Infix::Plus => Literal::BigInt(left + right),
+            Infix::Minus => Literal::BigInt(left - right),
+            Infix::Multiply => Literal::BigInt(left * right),
+            Infix::Divide => Literal::BigInt(left.checked_div(right)?),
+            _ => Literal::Boolean(compare(infix, left.cmp(right))?),
+        },
+        (Literal::Float(left), Literal::Float(right)) => {
+            let float = match infix {
+                Infix::Plus => left + right,
+                Infix::Minus => left - right,
+                Infix::Multiply => left * right,
+                Infix::Divide if *right != 0.0 => left / right,
+                Infix::Divide => return None,
+                _ => return compare(infix, left.partial_cmp(right)?).map(Literal::Boolean),
+            };
+            // There is no literal for NaN
+            if float.is_nan() {
+                return None;
+            }
+            Literal::Float(float)
+        }
+        (Literal::Boolean(left), Literal::Boolean(right)) => match infix {
+            Infix::Equal => Literal::Boolean(left == right),
+            Infix::NotEqual => Literal::Boolean(left != right),
+            _ => return None,
+        },
+        (Literal::String(left), Literal::String(right)) => match infix {
+            Infix::Plus => Literal::String(format!("{}{}", left, right)),
+            Infix::Equal => Literal::Boolean(left == right),
+            Infix::NotEqual => Literal::Boolean(left != right),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(literal)
+}
+// Result of a comparison operator, `None` for the arithmetic ones
+fn compare(infix: &Infix, ordering: Ordering) -> Option<bool> {
+    let result = match infix {
+        Infix::Equal => ordering == Ordering::Equal,
+        Infix::NotEqual => ordering != Ordering::Equal,
+        Infix::LessThan => ordering == Ordering::Less,
+        Infix::LessThanEqual => ordering != Ordering::Greater,
+        Infix::GreaterThan => ordering == Ordering::Greater,
+        Infix::GreaterThanEqual => ordering != Ordering::Less,
+        Infix::Plus | Infix::Minus | Infix::Multiply | Infix::Divide => return None,
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::printer::Parens;
+    use crate::parser::testing::parse;
+
+    fn optimized(input: &str) -> String {
+        optimize(parse(input)).display(Parens::Minimal).to_string()
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let tests = [
+            ("1 + 2 * 3", "7;"),
+            ("-(2 - 5) * a", "3 * a;"),
+            ("!(1 < 2) == false", "true;"),
+            ("10n * 10n - 1n", "99n;"),
+            ("1.5 * 2.0 > 2.5", "true;"),
+            ("7 / 2", "3;"),
+            // Left for the runtime to fail on
+            ("1 / 0", "1 / 0;"),
+            ("10n / (1n - 1n)", "10n / 0n;"),
+            ("9223372036854775807 + 1", "9223372036854775807 + 1;"),
+            ("1 + true", "1 + true;"),
+            ("2 * 3 + 1.0", "6 + 1.0;"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+    #[test]
+    fn test_dead_branches() {
+        let tests = [
+            ("if (true) { a } else { b }", "a;"),
+            (
+                "let x = if (1 > 2) { 1 } else { 2 + 2 }; x",
+                "let x = 4;\n4;",
+            ),
+            ("if (false) { a }; b", "b;"),
+            ("if (!false) { a; return b; }; c", "a;\nreturn b;\nc;"),
+            // Still the value of the program
+            ("a; if (false) { b }", "a;\nif (false) { b; };"),
+            // Its `let`s were already visible after the `if`
+            ("if (true) { let y = c; }; y", "let y = c;\ny;"),
+            // A dropped branch declares nothing
+            ("if (false) { let y = 1; }; y", "y;"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+    #[test]
+    fn test_inline_constants() {
+        let tests = [
+            (
+                "let x = 2; let y = x * 3; y + z",
+                "let x = 2;\nlet y = 6;\n6 + z;",
+            ),
+            ("let x = y; x + 1", "let x = y;\nx + 1;"),
+            (
+                "let x = 1; if (c) { let x = 2; x } else { x }",
+                "let x = 1;\nif (c) { let x = 2; 2; } else { 1; };",
+            ),
+            // `x` is only bound when `c` is true
+            ("if (c) { let x = 2; }; x", "if (c) { let x = 2; };\nx;"),
+            ("if (true) { let x = 2; }; x", "let x = 2;\nx;"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(optimized(input), expected, "{}", input);
+        }
+    }
+}
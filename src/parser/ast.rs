@@ -1,11 +1,48 @@
+use std::ops::{Index, IndexMut};
+
+use crate::bigint::BigInt;
+use crate::symbol::{Interner, Symbol};
+use crate::token::Span;
+
+// Handles to the nodes stored in a `Program`. They stay valid for the life of the program,
+// so analyses can keep their results in side tables keyed by id.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct ExprId(u32);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct StmtId(u32);
+
+// Either kind of node, for side tables that cover expressions and statements alike
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum NodeId {
+    Expr(ExprId),
+    Stmt(StmtId),
+}
+
+impl From<ExprId> for NodeId {
+    fn from(id: ExprId) -> Self {
+        NodeId::Expr(id)
+    }
+}
+impl From<StmtId> for NodeId {
+    fn from(id: StmtId) -> Self {
+        NodeId::Stmt(id)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
-pub struct Ident(pub String);
+pub struct Ident(pub Symbol);
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Prefix {
     Not,
     Minus,
 }
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Infix {
     Plus,
@@ -20,37 +57,393 @@ pub enum Infix {
     LessThan,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Expr {
     Ident(Ident),
     Literal(Literal),
-    Prefix(Prefix, Box<Expr>),
-    Infix(Box<Expr>, Infix, Box<Expr>),
+    Prefix(Prefix, ExprId),
+    Infix(ExprId, Infix, ExprId),
     If {
-        cond: Box<Expr>,
+        cond: ExprId,
         consequence: BlockStatement,
         alternative: Option<BlockStatement>,
     },
 }
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Literal {
     Int(i64),
+    BigInt(BigInt),
+    Float(f64),
     String(String),
     Boolean(bool),
 }
 
+// Type written in an annotation, checked by `types::check` and ignored otherwise
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Clone, Debug)]
+pub enum TypeExpr {
+    // `int`, `bool`, `any`, ...
+    Named(Ident),
+    // `[int]`
+    Array(Box<TypeExpr>),
+    // `{string: int}`
+    Hash(Box<TypeExpr>, Box<TypeExpr>),
+    // `fn(int, int) -> bool`
+    Function(Vec<TypeExpr>, Box<TypeExpr>),
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Statement {
     Blank,
-    Let(Ident, Expr),
-    Return(Option<Expr>),
-    Expr(Expr),
+    // `let name: annotation = value;`
+    Let(Ident, Option<TypeExpr>, ExprId),
+    Return(Option<ExprId>),
+    Expr(ExprId),
 }
 
-pub type BlockStatement = Vec<Statement>;
-pub type Program = Vec<Statement>;
+pub type BlockStatement = Vec<StmtId>;
+
+// Arena owning every node of a parsed program, children are referenced by id.
+// Dropping the program frees the whole tree at once.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "ProgramData")
+)]
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    exprs: Vec<Expr>,
+    stmts: Vec<Statement>,
+    // Source location of every node, indexed the same as the nodes
+    expr_spans: Vec<Span>,
+    stmt_spans: Vec<Span>,
+    // Names of the identifiers in the program
+    symbols: Interner,
+    // Top level statements in source order
+    pub statements: BlockStatement,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::default()
+    }
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        self.symbols.intern(name)
+    }
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.symbols[symbol]
+    }
+    pub fn symbols(&self) -> &Interner {
+        &self.symbols
+    }
+    pub fn alloc_expr(&mut self, expr: Expr, span: Span) -> ExprId {
+        self.exprs.push(expr);
+        self.expr_spans.push(span);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+    pub fn alloc_stmt(&mut self, stmt: Statement, span: Span) -> StmtId {
+        self.stmts.push(stmt);
+        self.stmt_spans.push(span);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+    // Allocates a statement and appends it to the top level
+    pub fn push(&mut self, stmt: Statement, span: Span) -> StmtId {
+        let id = self.alloc_stmt(stmt, span);
+        self.statements.push(id);
+        id
+    }
+
+    pub fn span(&self, id: impl Into<NodeId>) -> Span {
+        match id.into() {
+            NodeId::Expr(id) => self.expr_spans[id.0 as usize],
+            NodeId::Stmt(id) => self.stmt_spans[id.0 as usize],
+        }
+    }
+    pub fn set_span(&mut self, id: impl Into<NodeId>, span: Span) {
+        match id.into() {
+            NodeId::Expr(id) => self.expr_spans[id.0 as usize] = span,
+            NodeId::Stmt(id) => self.stmt_spans[id.0 as usize] = span,
+        }
+    }
+
+    // Number of top level statements
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+    // Top level statements in source order
+    pub fn iter(&self) -> impl Iterator<Item = &Statement> {
+        self.statements.iter().map(|id| &self[*id])
+    }
+    pub fn get(&self, index: usize) -> Option<&Statement> {
+        self.statements.get(index).map(|id| &self[*id])
+    }
+    // Every expression id in the arena, including nodes no statement refers to
+    pub fn expr_ids(&self) -> impl Iterator<Item = ExprId> {
+        (0..self.exprs.len() as u32).map(ExprId)
+    }
+    pub fn stmt_ids(&self) -> impl Iterator<Item = StmtId> {
+        (0..self.stmts.len() as u32).map(StmtId)
+    }
+
+    // Structural comparison of two subtrees, possibly from different programs.
+    // Spans are ignored, so a parsed tree compares equal to one built by hand.
+    pub fn expr_eq(&self, a: ExprId, other: &Program, b: ExprId) -> bool {
+        match (&self[a], &other[b]) {
+            (Expr::Ident(x), Expr::Ident(y)) => self.name(x.0) == other.name(y.0),
+            (Expr::Literal(x), Expr::Literal(y)) => x == y,
+            (Expr::Prefix(op_a, right_a), Expr::Prefix(op_b, right_b)) => {
+                op_a == op_b && self.expr_eq(*right_a, other, *right_b)
+            }
+            (Expr::Infix(left_a, op_a, right_a), Expr::Infix(left_b, op_b, right_b)) => {
+                op_a == op_b
+                    && self.expr_eq(*left_a, other, *left_b)
+                    && self.expr_eq(*right_a, other, *right_b)
+            }
+            (
+                Expr::If {
+                    cond: cond_a,
+                    consequence: cons_a,
+                    alternative: alt_a,
+                },
+                Expr::If {
+                    cond: cond_b,
+                    consequence: cons_b,
+                    alternative: alt_b,
+                },
+            ) => {
+                self.expr_eq(*cond_a, other, *cond_b)
+                    && self.block_eq(cons_a, other, cons_b)
+                    && match (alt_a, alt_b) {
+                        (Some(alt_a), Some(alt_b)) => self.block_eq(alt_a, other, alt_b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+    pub fn stmt_eq(&self, a: StmtId, other: &Program, b: StmtId) -> bool {
+        match (&self[a], &other[b]) {
+            (Statement::Blank, Statement::Blank) => true,
+            (Statement::Let(name_a, type_a, a), Statement::Let(name_b, type_b, b)) => {
+                self.name(name_a.0) == other.name(name_b.0)
+                    && match (type_a, type_b) {
+                        (Some(type_a), Some(type_b)) => self.type_eq(type_a, other, type_b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+                    && self.expr_eq(*a, other, *b)
+            }
+            (Statement::Return(Some(a)), Statement::Return(Some(b))) => self.expr_eq(*a, other, *b),
+            (Statement::Return(None), Statement::Return(None)) => true,
+            (Statement::Expr(a), Statement::Expr(b)) => self.expr_eq(*a, other, *b),
+            _ => false,
+        }
+    }
+    pub fn block_eq(&self, a: &[StmtId], other: &Program, b: &[StmtId]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.stmt_eq(*a, other, *b))
+    }
+    pub fn type_eq(&self, a: &TypeExpr, other: &Program, b: &TypeExpr) -> bool {
+        match (a, b) {
+            (TypeExpr::Named(a), TypeExpr::Named(b)) => self.name(a.0) == other.name(b.0),
+            (TypeExpr::Array(a), TypeExpr::Array(b)) => self.type_eq(a, other, b),
+            (TypeExpr::Hash(key_a, value_a), TypeExpr::Hash(key_b, value_b)) => {
+                self.type_eq(key_a, other, key_b) && self.type_eq(value_a, other, value_b)
+            }
+            (TypeExpr::Function(params_a, ret_a), TypeExpr::Function(params_b, ret_b)) => {
+                params_a.len() == params_b.len()
+                    && params_a
+                        .iter()
+                        .zip(params_b)
+                        .all(|(a, b)| self.type_eq(a, other, b))
+                    && self.type_eq(ret_a, other, ret_b)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Programs are equal when their trees are, regardless of spans or where the nodes sit in the arena
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.block_eq(&self.statements, other, &other.statements)
+    }
+}
+
+// Deserialized programs come from outside, check every id points into the arena before
+// handing out a `Program` that would otherwise panic when indexed
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ProgramData {
+    exprs: Vec<Expr>,
+    stmts: Vec<Statement>,
+    expr_spans: Vec<Span>,
+    stmt_spans: Vec<Span>,
+    symbols: Interner,
+    statements: BlockStatement,
+}
 
-#[derive(PartialEq, PartialOrd)]
+#[cfg(feature = "serde")]
+impl TryFrom<ProgramData> for Program {
+    type Error = String;
+
+    fn try_from(data: ProgramData) -> Result<Self, String> {
+        if data.exprs.len() != data.expr_spans.len() || data.stmts.len() != data.stmt_spans.len() {
+            return Err(String::from("every node needs exactly one span"));
+        }
+        let program = Program {
+            exprs: data.exprs,
+            stmts: data.stmts,
+            expr_spans: data.expr_spans,
+            stmt_spans: data.stmt_spans,
+            symbols: data.symbols,
+            statements: data.statements,
+        };
+        let mut state = ValidState {
+            exprs: vec![Visit::New; program.exprs.len()],
+            stmts: vec![Visit::New; program.stmts.len()],
+        };
+        // Unreferenced nodes are checked as well, `expr_ids` and `stmt_ids` still hand them out
+        for id in program.statements.iter().copied().chain(program.stmt_ids()) {
+            state.stmt(&program, id)?;
+        }
+        for id in program.expr_ids() {
+            state.expr(&program, id)?;
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Clone, Copy)]
+enum Visit {
+    New,
+    Open,
+    Done,
+}
+
+// Checks every id is in range and never leads back to a node that contains it
+#[cfg(feature = "serde")]
+struct ValidState {
+    exprs: Vec<Visit>,
+    stmts: Vec<Visit>,
+}
+
+#[cfg(feature = "serde")]
+impl ValidState {
+    fn enter(visits: &mut [Visit], index: u32, what: &str) -> Result<bool, String> {
+        match visits.get(index as usize) {
+            None => Err(format!("{} id {} is out of range", what, index)),
+            Some(Visit::Open) => Err(format!("{} {} contains itself", what, index)),
+            Some(Visit::Done) => Ok(false),
+            Some(Visit::New) => {
+                visits[index as usize] = Visit::Open;
+                Ok(true)
+            }
+        }
+    }
+    fn symbol(program: &Program, symbol: Symbol) -> Result<(), String> {
+        match program.symbols.contains(symbol) {
+            true => Ok(()),
+            false => Err(format!("{:?} is out of range", symbol)),
+        }
+    }
+    fn type_expr(program: &Program, annotation: &TypeExpr) -> Result<(), String> {
+        match annotation {
+            TypeExpr::Named(name) => Self::symbol(program, name.0),
+            TypeExpr::Array(element) => Self::type_expr(program, element),
+            TypeExpr::Hash(key, value) => {
+                Self::type_expr(program, key)?;
+                Self::type_expr(program, value)
+            }
+            TypeExpr::Function(params, ret) => {
+                for param in params {
+                    Self::type_expr(program, param)?;
+                }
+                Self::type_expr(program, ret)
+            }
+        }
+    }
+    fn stmt(&mut self, program: &Program, id: StmtId) -> Result<(), String> {
+        if !Self::enter(&mut self.stmts, id.0, "statement")? {
+            return Ok(());
+        }
+        match &program[id] {
+            Statement::Blank | Statement::Return(None) => {}
+            Statement::Let(name, annotation, value) => {
+                Self::symbol(program, name.0)?;
+                if let Some(annotation) = annotation {
+                    Self::type_expr(program, annotation)?;
+                }
+                self.expr(program, *value)?;
+            }
+            Statement::Return(Some(value)) | Statement::Expr(value) => {
+                self.expr(program, *value)?
+            }
+        }
+        self.stmts[id.0 as usize] = Visit::Done;
+        Ok(())
+    }
+    fn expr(&mut self, program: &Program, id: ExprId) -> Result<(), String> {
+        if !Self::enter(&mut self.exprs, id.0, "expression")? {
+            return Ok(());
+        }
+        match &program[id] {
+            Expr::Ident(ident) => Self::symbol(program, ident.0)?,
+            Expr::Literal(_) => {}
+            Expr::Prefix(_, right) => self.expr(program, *right)?,
+            Expr::Infix(left, _, right) => {
+                self.expr(program, *left)?;
+                self.expr(program, *right)?;
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.expr(program, *cond)?;
+                for stmt in consequence.iter().chain(alternative.iter().flatten()) {
+                    self.stmt(program, *stmt)?;
+                }
+            }
+        }
+        self.exprs[id.0 as usize] = Visit::Done;
+        Ok(())
+    }
+}
+
+impl Index<ExprId> for Program {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+}
+impl IndexMut<ExprId> for Program {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.0 as usize]
+    }
+}
+impl Index<StmtId> for Program {
+    type Output = Statement;
+
+    fn index(&self, id: StmtId) -> &Statement {
+        &self.stmts[id.0 as usize]
+    }
+}
+impl IndexMut<StmtId> for Program {
+    fn index_mut(&mut self, id: StmtId) -> &mut Statement {
+        &mut self.stmts[id.0 as usize]
+    }
+}
+
+#[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
 pub enum Precedence {
     Lowest,
     Equals,      // ==
@@ -60,3 +453,66 @@ pub enum Precedence {
     Prefix,      // -x or !x
     Call,        // my_function(x)
 }
+
+impl Precedence {
+    // The next tighter binding level
+    pub fn next(self) -> Precedence {
+        match self {
+            Precedence::Lowest => Precedence::Equals,
+            Precedence::Equals => Precedence::LessGreater,
+            Precedence::LessGreater => Precedence::Sum,
+            Precedence::Sum => Precedence::Product,
+            Precedence::Product => Precedence::Prefix,
+            Precedence::Prefix | Precedence::Call => Precedence::Call,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::parser::testing::parse;
+
+    #[test]
+    fn test_json_round_trip() {
+        let input =
+            "let x = 1 + 2.5; if (!x) { return 123456789012345678901234567890n; } else { y }";
+        let program = parse(input);
+        let json = serde_json::to_string(&program).unwrap();
+        let back: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, program);
+        for id in program.stmt_ids() {
+            assert_eq!(back.span(id), program.span(id));
+        }
+        assert!(json.contains(r#"{"Ident":0}"#), "{}", json);
+        assert!(json.contains(r#""symbols":["x","y"]"#), "{}", json);
+        assert!(
+            json.contains(r#""123456789012345678901234567890""#),
+            "{}",
+            json
+        );
+    }
+    #[test]
+    fn test_json_rejects_bad_ids() {
+        let span = serde_json::to_string(&Span::default()).unwrap();
+        let with_symbols = |exprs: &str, symbols: &str, statements: &str| {
+            let json = format!(
+                r#"{{"exprs":{},"stmts":[{{"Expr":0}}],"expr_spans":[{}],"stmt_spans":[{}],"symbols":{},"statements":{}}}"#,
+                exprs, span, span, symbols, statements
+            );
+            serde_json::from_str::<Program>(&json).map_err(|err| err.to_string())
+        };
+        let program = |exprs: &str, statements: &str| with_symbols(exprs, r#"["x"]"#, statements);
+        assert!(program(r#"[{"Ident":0}]"#, "[0]").is_ok());
+        let err = program(r#"[{"Ident":1}]"#, "[0]").unwrap_err();
+        assert!(err.contains("Symbol(1) is out of range"), "{}", err);
+        let err = with_symbols(r#"[{"Ident":0}]"#, r#"["x","x"]"#, "[0]").unwrap_err();
+        assert!(err.contains(r#"the name "x" is listed twice"#), "{}", err);
+        let err = program(r#"[{"Prefix":["Not",3]}]"#, "[0]").unwrap_err();
+        assert!(err.contains("expression id 3 is out of range"), "{}", err);
+        let err = program(r#"[{"Prefix":["Not",0]}]"#, "[0]").unwrap_err();
+        assert!(err.contains("expression 0 contains itself"), "{}", err);
+        let err = program(r#"[{"Ident":0}]"#, "[1]").unwrap_err();
+        assert!(err.contains("statement id 1 is out of range"), "{}", err);
+    }
+}
@@ -1,23 +1,42 @@
-use crate::{lexer::Lexer, token::Token};
+use std::fmt::Write;
+use std::mem;
+
+use crate::{
+    lexer::Lexer,
+    suggest::closest,
+    token::{Span, Token, KEYWORDS},
+};
 
 use self::ast::*;
-mod ast;
-type ParseError = String;
-type ParseErrors = Vec<ParseError>;
-struct Parser<'a> {
+pub mod ast;
+pub mod printer;
+mod sexpr;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod visit;
+pub type ParseError = String;
+pub type ParseErrors = Vec<ParseError>;
+pub struct Parser<'a> {
     lexer: Lexer<'a>,
-    curr_token: Token,
-    next_token: Token,
+    curr_token: Token<'a>,
+    next_token: Token<'a>,
+    curr_span: Span,
+    next_span: Span,
     errors: ParseErrors,
+    // Arena the nodes are allocated in, handed out by `parse_program`
+    program: Program,
 }
 
 impl<'a> Parser<'a> {
-    fn new(lexer: Lexer<'a>) -> Self {
+    pub fn new(lexer: Lexer<'a>) -> Self {
         let mut parser = Parser {
             lexer,
             curr_token: Token::Eof,
             next_token: Token::Eof,
+            curr_span: Span::default(),
+            next_span: Span::default(),
             errors: vec![],
+            program: Program::new(),
         };
         parser.next_token();
         parser.next_token();
@@ -27,11 +46,17 @@ impl<'a> Parser<'a> {
     pub fn get_errors(&self) -> ParseErrors {
         self.errors.clone()
     }
-    fn peek_error(&mut self, token: Token) {
-        let msg = format!(
+    fn peek_error(&mut self, token: Token<'_>) {
+        let mut msg = format!(
             "Expected next token to be {:?}, got {:?} instead",
             token, self.next_token
         );
+        // Most often a misspelled keyword
+        if let Token::Ident(name) = &self.next_token {
+            if let Some(keyword) = closest(name, KEYWORDS.iter().copied()) {
+                write!(msg, ", did you mean `{}`?", keyword).unwrap();
+            }
+        }
         self.errors.push(msg);
     }
 
@@ -42,39 +67,64 @@ impl<'a> Parser<'a> {
         get_precedence(&self.curr_token)
     }
     fn next_token(&mut self) {
-        // TODO: Check if the commented line works instead of cloning
-        // self.curr_token = mem::replace(&mut self.peek_token, Token::Illegal);
-        self.curr_token = self.next_token.clone();
-        self.next_token = self.lexer.next_token();
+        let next = self.lexer.next_spanned_token();
+        self.curr_token = mem::replace(&mut self.next_token, next.token);
+        self.curr_span = mem::replace(&mut self.next_span, next.span);
+        for err in self.lexer.take_errors() {
+            self.errors.push(err.to_string());
+        }
     }
-    fn parse_program(&mut self) -> Program {
-        let mut program = Program::new();
+    pub fn parse_program(&mut self) -> Program {
+        let mut statements = BlockStatement::new();
         while !self.curr_token_is(Token::Eof) {
             let statement = self.parse_statement();
 
             if let Some(val) = statement {
-                program.push(val);
+                statements.push(val);
             }
             self.next_token();
         }
+        let mut program = mem::take(&mut self.program);
+        program.statements = statements;
         program
     }
-    fn parse_statement(&mut self) -> Option<Statement> {
+    fn parse_statement(&mut self) -> Option<StmtId> {
         match self.curr_token {
             Token::Let => self.parse_let_statement(),
             Token::Return => self.parse_return_statement(),
+            // An empty statement
+            Token::Semicolon => None,
             _ => self.parse_expr_statement(),
         }
     }
-    fn parse_let_statement(&mut self) -> Option<Statement> {
+    // Span from `start` up to and including the current token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.curr_span)
+    }
+    fn parse_let_statement(&mut self) -> Option<StmtId> {
+        let start = self.curr_span;
         match &self.next_token {
             Token::Ident(_) => self.next_token(),
-            _ => return None,
+            _ => {
+                let msg = format!(
+                    "Expected next token to be an identifier, got {:?} instead",
+                    self.next_token
+                );
+                self.errors.push(msg);
+                return None;
+            }
         };
         let name = match self.parse_ident() {
             Some(name) => name,
             _ => return None,
         };
+        let annotation = if self.next_token_is(&Token::Colon) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
         if !self.expect_next_token(Token::Assign) {
             return None;
         }
@@ -83,42 +133,110 @@ impl<'a> Parser<'a> {
             Some(val) => val,
             _ => return None,
         };
-        while !self.curr_token_is(Token::Semicolon) {
+        if self.next_token_is(&Token::Semicolon) {
             self.next_token();
         }
-        Some(Statement::Let(name, expression))
+        let span = self.span_from(start);
+        Some(
+            self.program
+                .alloc_stmt(Statement::Let(name, annotation, expression), span),
+        )
+    }
+    // Type annotation starting at the current token, which is left on the type's last token
+    fn parse_type(&mut self) -> Option<TypeExpr> {
+        match &self.curr_token {
+            Token::Ident(name) => Some(TypeExpr::Named(Ident(self.program.intern(name)))),
+            Token::Lbracket => {
+                self.next_token();
+                let element = self.parse_type()?;
+                if !self.expect_next_token(Token::Rbracket) {
+                    return None;
+                }
+                Some(TypeExpr::Array(Box::new(element)))
+            }
+            Token::Lbrace => {
+                self.next_token();
+                let key = self.parse_type()?;
+                if !self.expect_next_token(Token::Colon) {
+                    return None;
+                }
+                self.next_token();
+                let value = self.parse_type()?;
+                if !self.expect_next_token(Token::Rbrace) {
+                    return None;
+                }
+                Some(TypeExpr::Hash(Box::new(key), Box::new(value)))
+            }
+            Token::Function => {
+                if !self.expect_next_token(Token::Lparen) {
+                    return None;
+                }
+                let mut params = vec![];
+                if self.next_token_is(&Token::Rparen) {
+                    self.next_token();
+                } else {
+                    loop {
+                        self.next_token();
+                        params.push(self.parse_type()?);
+                        if self.next_token_is(&Token::Comma) {
+                            self.next_token();
+                        } else if self.expect_next_token(Token::Rparen) {
+                            break;
+                        } else {
+                            return None;
+                        }
+                    }
+                }
+                if !self.expect_next_token(Token::Arrow) {
+                    return None;
+                }
+                self.next_token();
+                let ret = self.parse_type()?;
+                Some(TypeExpr::Function(params, Box::new(ret)))
+            }
+            token => {
+                let msg = format!("Expected a type, got {:?} instead", token);
+                self.errors.push(msg);
+                None
+            }
+        }
     }
-    fn parse_return_statement(&mut self) -> Option<Statement> {
-        self.next_token();
-        let expression = self.parse_expr(Precedence::Lowest);
-        while !self.curr_token_is(Token::Semicolon) {
+    fn parse_return_statement(&mut self) -> Option<StmtId> {
+        let start = self.curr_span;
+        // Anything else is left for the next statement, `return` on its own returns nothing
+        let expression = if starts_expr(&self.next_token) {
+            self.next_token();
+            Some(self.parse_expr(Precedence::Lowest)?)
+        } else {
+            None
+        };
+        if self.next_token_is(&Token::Semicolon) {
             self.next_token();
         }
-        Some(Statement::Return(expression))
+        let span = self.span_from(start);
+        Some(self.program.alloc_stmt(Statement::Return(expression), span))
     }
-    fn parse_expr_statement(&mut self) -> Option<Statement> {
+    fn parse_expr_statement(&mut self) -> Option<StmtId> {
+        let start = self.curr_span;
         match self.parse_expr(Precedence::Lowest) {
             Some(expr) => {
                 if self.next_token_is(&Token::Semicolon) {
                     self.next_token();
                 }
-                Some(Statement::Expr(expr))
+                let span = self.span_from(start);
+                Some(self.program.alloc_stmt(Statement::Expr(expr), span))
             }
             None => None,
         }
     }
-    fn curr_token_is(&self, token: Token) -> bool {
+    fn curr_token_is(&self, token: Token<'_>) -> bool {
         self.curr_token == token
     }
-    fn next_token_is(&self, token: &Token) -> bool {
+    fn next_token_is(&self, token: &Token<'_>) -> bool {
         self.next_token == *token
     }
-    fn expect_next_token(&mut self, token: Token) -> bool {
+    fn expect_next_token(&mut self, token: Token<'_>) -> bool {
         if self.next_token_is(&token) {
-            println!(
-                "expect peek, curr {:?} next {:?}",
-                self.curr_token, self.next_token
-            );
             self.next_token();
             true
         } else {
@@ -127,22 +245,29 @@ impl<'a> Parser<'a> {
         }
     }
     fn parse_ident(&mut self) -> Option<Ident> {
-        match self.curr_token {
-            Token::Ident(ref mut ident) => Some(Ident(ident.clone())),
+        match &self.curr_token {
+            Token::Ident(name) => Some(Ident(self.program.intern(name))),
             _ => None,
         }
     }
-    fn parse_expr(&mut self, precedence: Precedence) -> Option<Expr> {
+    fn parse_expr(&mut self, precedence: Precedence) -> Option<ExprId> {
         // prefix
         let mut left = match self.curr_token {
             Token::Ident(_) => self.parse_ident_expr(),
-            Token::Int(_) => self.parse_int_expr(),
+            Token::Int(_) | Token::BigInt(_) => self.parse_int_expr(),
+            Token::Float(_) => self.parse_float_expr(),
             Token::Bang => self.parse_prefix_expr(),
             Token::Minus => self.parse_prefix_expr(),
             Token::Boolean(_) => self.parse_bool_expr(),
             Token::Lparen => self.parse_grouped_expr(),
             Token::If => self.parse_if_expr(),
-            _ => None,
+            // The lexer reports every illegal token
+            Token::Illegal => None,
+            _ => {
+                let msg = format!("No prefix parse function for {:?} found", self.curr_token);
+                self.errors.push(msg);
+                None
+            }
         };
         // infix
         while !self.next_token_is(&Token::Semicolon) && precedence < self.next_precedence() {
@@ -155,39 +280,57 @@ impl<'a> Parser<'a> {
                 | Token::NotEq
                 | Token::Lt
                 | Token::Gt => {
+                    // Nothing to apply the operator to, e.g. `return;` followed by `-x`
+                    let left_expr = left?;
                     self.next_token();
-                    left = self.parse_infix_expr(left.unwrap());
+                    left = self.parse_infix_expr(left_expr);
                 }
                 _ => return left,
             }
         }
         left
     }
-    fn parse_grouped_expr(&mut self) -> Option<Expr> {
+    fn parse_grouped_expr(&mut self) -> Option<ExprId> {
+        let start = self.curr_span;
         self.next_token();
         let expr = self.parse_expr(Precedence::Lowest);
         if !self.expect_next_token(Token::Rparen) {
-            None
-        } else {
-            expr
+            return None;
         }
+        // Widen the span over the parentheses so enclosing nodes cover them too
+        let expr = expr?;
+        let span = self.span_from(start);
+        self.program.set_span(expr, span);
+        Some(expr)
     }
-    fn parse_bool_expr(&mut self) -> Option<Expr> {
-        match self.curr_token {
-            Token::Boolean(b) => Some(Expr::Literal(Literal::Boolean(b))),
-            _ => None,
-        }
+    fn parse_bool_expr(&mut self) -> Option<ExprId> {
+        let expr = match self.curr_token {
+            Token::Boolean(b) => Expr::Literal(Literal::Boolean(b)),
+            _ => return None,
+        };
+        Some(self.program.alloc_expr(expr, self.curr_span))
     }
-    fn parse_ident_expr(&mut self) -> Option<Expr> {
-        self.parse_ident().map(Expr::Ident)
+    fn parse_ident_expr(&mut self) -> Option<ExprId> {
+        let ident = self.parse_ident()?;
+        Some(self.program.alloc_expr(Expr::Ident(ident), self.curr_span))
     }
-    fn parse_int_expr(&mut self) -> Option<Expr> {
-        match self.curr_token {
-            Token::Int(int) => Some(Expr::Literal(Literal::Int(int))),
-            _ => None,
-        }
+    fn parse_int_expr(&mut self) -> Option<ExprId> {
+        let expr = match self.curr_token {
+            Token::Int(int) => Expr::Literal(Literal::Int(int)),
+            Token::BigInt(ref int) => Expr::Literal(Literal::BigInt(int.clone())),
+            _ => return None,
+        };
+        Some(self.program.alloc_expr(expr, self.curr_span))
     }
-    fn parse_prefix_expr(&mut self) -> Option<Expr> {
+    fn parse_float_expr(&mut self) -> Option<ExprId> {
+        let expr = match self.curr_token {
+            Token::Float(float) => Expr::Literal(Literal::Float(float)),
+            _ => return None,
+        };
+        Some(self.program.alloc_expr(expr, self.curr_span))
+    }
+    fn parse_prefix_expr(&mut self) -> Option<ExprId> {
+        let start = self.curr_span;
         let left = match self.curr_token {
             Token::Bang => Prefix::Not,
             Token::Minus => Prefix::Minus,
@@ -199,9 +342,10 @@ impl<'a> Parser<'a> {
             _ => return None,
         };
 
-        Some(Expr::Prefix(left, Box::new(right)))
+        let span = self.span_from(start);
+        Some(self.program.alloc_expr(Expr::Prefix(left, right), span))
     }
-    fn parse_infix_expr(&mut self, left: Expr) -> Option<Expr> {
+    fn parse_infix_expr(&mut self, left: ExprId) -> Option<ExprId> {
         let infix = match self.curr_token {
             Token::Plus => Infix::Plus,
             Token::Minus => Infix::Minus,
@@ -216,15 +360,20 @@ impl<'a> Parser<'a> {
 
         let precedence = self.curr_precedence();
         self.next_token();
-        self.parse_expr(precedence)
-            .map(|expr| Expr::Infix(Box::new(left), infix, Box::new(expr)))
-    }
-    fn parse_if_expr(&mut self) -> Option<Expr> {
+        let right = self.parse_expr(precedence)?;
+        let span = self.span_from(self.program.span(left));
+        Some(
+            self.program
+                .alloc_expr(Expr::Infix(left, infix, right), span),
+        )
+    }
+    fn parse_if_expr(&mut self) -> Option<ExprId> {
+        let start = self.curr_span;
         if !self.expect_next_token(Token::Lparen) {
             return None;
         }
         self.next_token();
-        let cond = self.parse_expr(Precedence::Lowest);
+        let cond = self.parse_expr(Precedence::Lowest)?;
         if !self.expect_next_token(Token::Rparen) {
             return None;
         }
@@ -236,19 +385,20 @@ impl<'a> Parser<'a> {
 
         if self.next_token_is(&Token::Else) {
             self.next_token();
-            if !self.next_token_is(&Token::Lbrace) {
+            if !self.expect_next_token(Token::Lbrace) {
                 return None;
             }
             alternative = Some(self.parse_block_statement());
         }
         let expr = Expr::If {
-            cond: Box::new(cond.unwrap()),
+            cond,
             consequence,
             alternative,
         };
-        Some(expr)
+        let span = self.span_from(start);
+        Some(self.program.alloc_expr(expr, span))
     }
-    fn parse_block_statement(&mut self) -> Vec<Statement> {
+    fn parse_block_statement(&mut self) -> BlockStatement {
         let mut block = Vec::new();
         self.next_token();
         while !self.curr_token_is(Token::Rbrace) && !self.curr_token_is(Token::Eof) {
@@ -262,7 +412,23 @@ impl<'a> Parser<'a> {
     }
 }
 
-fn get_precedence(token: &Token) -> Precedence {
+// Whether `token` has a prefix parse function
+fn starts_expr(token: &Token<'_>) -> bool {
+    matches!(
+        token,
+        Token::Ident(_)
+            | Token::Int(_)
+            | Token::BigInt(_)
+            | Token::Float(_)
+            | Token::Bang
+            | Token::Minus
+            | Token::Boolean(_)
+            | Token::Lparen
+            | Token::If
+    )
+}
+
+fn get_precedence(token: &Token<'_>) -> Precedence {
     match token {
         Token::Eq | Token::NotEq => Precedence::Equals,
         Token::Lt | Token::Gt => Precedence::LessGreater,
@@ -277,6 +443,7 @@ mod tests {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use crate::token::FileId;
     fn check_errors(parser: Parser) {
         let errors = parser.get_errors();
         if errors.is_empty() {
@@ -288,6 +455,16 @@ mod tests {
         }
         panic!("Failed")
     }
+    fn lit(program: &mut Program, literal: Literal) -> ExprId {
+        program.alloc_expr(Expr::Literal(literal), Span::default())
+    }
+    fn int(program: &mut Program, int: i64) -> ExprId {
+        lit(program, Literal::Int(int))
+    }
+    fn ident(program: &mut Program, name: &str) -> ExprId {
+        let name = Ident(program.intern(name));
+        program.alloc_expr(Expr::Ident(name), Span::default())
+    }
     #[test]
     fn check_parse_errors() {
         let input = r#"
@@ -299,18 +476,13 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert_eq!(3, program.len());
-        let tests = [
-            Statement::Let(Ident(String::from("x")), Expr::Literal(Literal::Int(5))),
-            Statement::Let(Ident(String::from("y")), Expr::Literal(Literal::Int(10))),
-            Statement::Let(
-                Ident(String::from("foobar")),
-                Expr::Literal(Literal::Int(123412345)),
-            ),
-        ];
-        for (i, test) in tests.iter().enumerate() {
-            let curr = program.get(i).unwrap();
-            assert_eq!(curr, test)
+        let mut expected = Program::new();
+        for (name, value) in [("x", 5), ("y", 10), ("foobar", 123412345)] {
+            let value = int(&mut expected, value);
+            let name = Ident(expected.intern(name));
+            expected.push(Statement::Let(name, None, value), Span::default());
         }
+        assert_eq!(program, expected);
     }
     #[test]
     fn test_returns() {
@@ -323,26 +495,191 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert_eq!(3, program.len());
-        let test = [
-            Statement::Return(Some(Expr::Literal(Literal::Int(5)))),
-            Statement::Return(Some(Expr::Literal(Literal::Int(10)))),
-            Statement::Return(None),
+        let mut expected = Program::new();
+        let five = int(&mut expected, 5);
+        expected.push(Statement::Return(Some(five)), Span::default());
+        let ten = int(&mut expected, 10);
+        expected.push(Statement::Return(Some(ten)), Span::default());
+        expected.push(Statement::Return(None), Span::default());
+        assert_eq!(program, expected);
+    }
+    #[test]
+    fn test_optional_semicolons() {
+        let input = "let x = 5\nreturn x\nlet y = 6";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_errors(parser);
+        let mut expected = Program::new();
+        let five = int(&mut expected, 5);
+        let x = Ident(expected.intern("x"));
+        expected.push(Statement::Let(x, None, five), Span::default());
+        let x = ident(&mut expected, "x");
+        expected.push(Statement::Return(Some(x)), Span::default());
+        let six = int(&mut expected, 6);
+        let y = Ident(expected.intern("y"));
+        expected.push(Statement::Let(y, None, six), Span::default());
+        assert_eq!(program, expected);
+
+        let mut parser = Parser::new(Lexer::new("let = 5;"));
+        parser.parse_program();
+        assert_eq!(
+            parser.get_errors(),
+            vec![
+                "Expected next token to be an identifier, got Assign instead",
+                "No prefix parse function for Assign found",
+            ]
+        );
+    }
+    #[test]
+    fn test_unexpected_tokens() {
+        let tests = [
+            (
+                "let a = 1;\n)\nlet b = 2;",
+                "No prefix parse function for Rparen found",
+            ),
+            (
+                "{ * [ } true : = , * -",
+                "No prefix parse function for Lbrace found",
+            ),
+            ("let x = ;", "No prefix parse function for Semicolon found"),
+            (
+                "return 1 + ;",
+                "No prefix parse function for Semicolon found",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.get_errors()[0], expected, "{}", input);
+        }
+    }
+    #[test]
+    fn test_return_without_value() {
+        let mut parser = Parser::new(Lexer::new("return\nreturn 2.5;; return }"));
+        let program = parser.parse_program();
+        assert_eq!(
+            program.display(printer::Parens::Minimal).to_string(),
+            "return;\nreturn 2.5;\nreturn;"
+        );
+        assert_eq!(
+            parser.get_errors(),
+            vec!["No prefix parse function for Rbrace found"]
+        );
+    }
+    #[test]
+    fn test_type_annotations() {
+        let input = "let x: int = 5;\nlet f: fn([int], {string: bool}) -> fn() -> any = g";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_errors(parser);
+        let named = |name| TypeExpr::Named(Ident(program.symbols().get(name).unwrap()));
+        let annotations: Vec<Option<TypeExpr>> = program
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Let(_, annotation, _) => annotation.clone(),
+                _ => panic!("expected a let statement"),
+            })
+            .collect();
+        assert_eq!(
+            annotations,
+            vec![
+                Some(named("int")),
+                Some(TypeExpr::Function(
+                    vec![
+                        TypeExpr::Array(Box::new(named("int"))),
+                        TypeExpr::Hash(Box::new(named("string")), Box::new(named("bool"))),
+                    ],
+                    Box::new(TypeExpr::Function(vec![], Box::new(named("any")))),
+                )),
+            ]
+        );
+        assert_eq!(
+            program.display(printer::Parens::Minimal).to_string(),
+            "let x: int = 5;\nlet f: fn([int], {string: bool}) -> fn() -> any = g;"
+        );
+
+        let tests = [
+            ("let x: = 5;", "Expected a type, got Assign instead"),
+            (
+                "let x: [int = 5;",
+                "Expected next token to be Rbracket, got Assign instead",
+            ),
+            (
+                "let f: fn(int) = 5;",
+                "Expected next token to be Arrow, got Assign instead",
+            ),
         ];
-        for (i, st) in program.iter().enumerate() {
-            assert_eq!(st, test.get(i).unwrap())
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.get_errors()[0], expected, "{}", input);
         }
     }
     #[test]
+    fn test_keyword_suggestion() {
+        let mut parser = Parser::new(Lexer::new("if (a) els { b }"));
+        parser.parse_program();
+        assert_eq!(
+            parser.get_errors()[0],
+            "Expected next token to be Lbrace, got Ident(\"els\") instead, did you mean `else`?"
+        );
+    }
+    #[test]
+    fn test_int_literal_overflow() {
+        let input = "let x = 99999999999999999999;";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(program.is_empty());
+        assert_eq!(
+            parser.get_errors(),
+            vec![String::from(
+                "Integer literal 99999999999999999999 at 1:9 is out of range for a 64-bit integer, use 99999999999999999999n for a big integer"
+            )]
+        );
+    }
+    #[test]
+    fn test_big_int_literal() {
+        let input = "let x = 99999999999999999999n;";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_errors(parser);
+        let mut expected = Program::new();
+        let value = lit(
+            &mut expected,
+            Literal::BigInt("99999999999999999999".parse().unwrap()),
+        );
+        let x = Ident(expected.intern("x"));
+        expected.push(Statement::Let(x, None, value), Span::default());
+        assert_eq!(program, expected);
+    }
+    #[test]
+    fn test_float_literal() {
+        let input = "2.5 * -1e3;";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_errors(parser);
+        let mut expected = Program::new();
+        let left = lit(&mut expected, Literal::Float(2.5));
+        let thousand = lit(&mut expected, Literal::Float(1000.0));
+        let right = expected.alloc_expr(Expr::Prefix(Prefix::Minus, thousand), Span::default());
+        let infix = expected.alloc_expr(Expr::Infix(left, Infix::Multiply, right), Span::default());
+        expected.push(Statement::Expr(infix), Span::default());
+        assert_eq!(program, expected);
+    }
+    #[test]
     fn test_expression() {
         let input = "foobar;";
         let mut parser = Parser::new(Lexer::new(input));
         let program = parser.parse_program();
         check_errors(parser);
         assert_eq!(program.len(), 1);
-        assert_eq!(
-            Statement::Expr(Expr::Ident(Ident(String::from("foobar")))),
-            program[0]
-        );
+        let Statement::Expr(expr) = program[program.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expr::Ident(ident) = &program[expr] else {
+            panic!("expected an identifier");
+        };
+        assert_eq!(program.name(ident.0), "foobar");
     }
     #[test]
     fn test_prefix() {
@@ -356,34 +693,19 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert!(!program.is_empty());
-        assert_eq!(
-            *program.get(0).unwrap(),
-            Statement::Expr(Expr::Prefix(
-                Prefix::Not,
-                Box::new(Expr::Literal(Literal::Int(5)))
-            ))
-        );
-        assert_eq!(
-            *program.get(1).unwrap(),
-            Statement::Expr(Expr::Prefix(
-                Prefix::Minus,
-                Box::new(Expr::Literal(Literal::Int(15)))
-            ))
-        );
-        assert_eq!(
-            *program.get(2).unwrap(),
-            Statement::Expr(Expr::Prefix(
-                Prefix::Not,
-                Box::new(Expr::Literal(Literal::Boolean(true)))
-            ))
-        );
-        assert_eq!(
-            *program.get(3).unwrap(),
-            Statement::Expr(Expr::Prefix(
-                Prefix::Not,
-                Box::new(Expr::Literal(Literal::Boolean(false)))
-            ))
-        );
+        let tests = [
+            (Prefix::Not, Literal::Int(5)),
+            (Prefix::Minus, Literal::Int(15)),
+            (Prefix::Not, Literal::Boolean(true)),
+            (Prefix::Not, Literal::Boolean(false)),
+        ];
+        let mut expected = Program::new();
+        for (prefix, literal) in tests {
+            let right = lit(&mut expected, literal);
+            let expr = expected.alloc_expr(Expr::Prefix(prefix, right), Span::default());
+            expected.push(Statement::Expr(expr), Span::default());
+        }
+        assert_eq!(program, expected);
     }
     #[test]
     fn test_infix() {
@@ -403,67 +725,37 @@ mod tests {
         let mut parser = Parser::new(Lexer::new(input));
         let program = parser.parse_program();
         check_errors(parser);
-        let test = [
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::Plus,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::Minus,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::Multiply,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::Divide,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::GreaterThan,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::LessThan,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::Equal,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Int(5))),
-                Infix::NotEqual,
-                Box::new(Expr::Literal(Literal::Int(5))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Boolean(true))),
-                Infix::Equal,
-                Box::new(Expr::Literal(Literal::Boolean(true))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Boolean(false))),
+        let five = || Literal::Int(5);
+        let tests = [
+            (five(), Infix::Plus, five()),
+            (five(), Infix::Minus, five()),
+            (five(), Infix::Multiply, five()),
+            (five(), Infix::Divide, five()),
+            (five(), Infix::GreaterThan, five()),
+            (five(), Infix::LessThan, five()),
+            (five(), Infix::Equal, five()),
+            (five(), Infix::NotEqual, five()),
+            (Literal::Boolean(true), Infix::Equal, Literal::Boolean(true)),
+            (
+                Literal::Boolean(false),
                 Infix::Equal,
-                Box::new(Expr::Literal(Literal::Boolean(false))),
-            )),
-            Statement::Expr(Expr::Infix(
-                Box::new(Expr::Literal(Literal::Boolean(true))),
+                Literal::Boolean(false),
+            ),
+            (
+                Literal::Boolean(true),
                 Infix::NotEqual,
-                Box::new(Expr::Literal(Literal::Boolean(false))),
-            )),
+                Literal::Boolean(false),
+            ),
         ];
-        assert!(!program.is_empty());
-        for (i, statement) in program.iter().enumerate() {
-            assert_eq!(statement, test.get(i).unwrap())
+        let mut expected = Program::new();
+        for (left, infix, right) in tests {
+            let left = lit(&mut expected, left);
+            let right = lit(&mut expected, right);
+            let expr = expected.alloc_expr(Expr::Infix(left, infix, right), Span::default());
+            expected.push(Statement::Expr(expr), Span::default());
         }
+        assert!(!program.is_empty());
+        assert_eq!(program, expected);
     }
     #[test]
     fn test_grouped() {
@@ -475,7 +767,8 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert!(!program.is_empty());
-        assert_eq!(*program.get(0).unwrap(), *program.get(1).unwrap());
+        let stmts = &program.statements;
+        assert!(program.stmt_eq(stmts[0], &program, stmts[1]));
     }
     #[test]
     fn test_bool() {
@@ -489,8 +782,10 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert!(!program.is_empty());
-        assert_eq!(*program.get(0).unwrap(), *program.get(1).unwrap());
-        assert_eq!(*program.get(2).unwrap(), *program.get(3).unwrap());
+        let stmts = &program.statements;
+        assert!(program.stmt_eq(stmts[0], &program, stmts[1]));
+        assert!(program.stmt_eq(stmts[2], &program, stmts[3]));
+        assert!(!program.stmt_eq(stmts[0], &program, stmts[2]));
     }
     #[test]
     fn test_if() {
@@ -502,29 +797,77 @@ mod tests {
         let program = parser.parse_program();
         check_errors(parser);
         assert!(!program.is_empty());
-        assert_eq!(
-            *program.get(0).unwrap(),
-            Statement::Expr(Expr::If {
-                cond: Box::new(Expr::Infix(
-                    Box::new(Expr::Ident(Ident("x".to_string()))),
-                    Infix::LessThan,
-                    Box::new(Expr::Ident(Ident("y".to_string()))),
-                )),
-                consequence: vec![Statement::Expr(Expr::Ident(Ident("x".to_string())))],
-                alternative: None
-            })
-        );
-        assert_eq!(
-            *program.get(1).unwrap(),
-            Statement::Expr(Expr::If {
-                cond: Box::new(Expr::Infix(
-                    Box::new(Expr::Ident(Ident("x".to_string()))),
-                    Infix::LessThan,
-                    Box::new(Expr::Ident(Ident("y".to_string()))),
-                )),
-                consequence: vec![Statement::Expr(Expr::Ident(Ident("x".to_string())))],
-                alternative: Some(vec![Statement::Expr(Expr::Ident(Ident("y".to_string())))])
-            })
-        );
+        let mut expected = Program::new();
+        for with_else in [false, true] {
+            let x = ident(&mut expected, "x");
+            let y = ident(&mut expected, "y");
+            let cond = expected.alloc_expr(Expr::Infix(x, Infix::LessThan, y), Span::default());
+            let x = ident(&mut expected, "x");
+            let consequence = vec![expected.alloc_stmt(Statement::Expr(x), Span::default())];
+            let alternative = if with_else {
+                let y = ident(&mut expected, "y");
+                Some(vec![
+                    expected.alloc_stmt(Statement::Expr(y), Span::default())
+                ])
+            } else {
+                None
+            };
+            let expr = expected.alloc_expr(
+                Expr::If {
+                    cond,
+                    consequence,
+                    alternative,
+                },
+                Span::default(),
+            );
+            expected.push(Statement::Expr(expr), Span::default());
+        }
+        assert_eq!(program, expected);
+    }
+    #[test]
+    fn test_node_ids() {
+        let input = "let x = 1 + 2; x";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_errors(parser);
+        // Every node can be addressed by id, so side tables can be keyed by them
+        assert_eq!(program.expr_ids().count(), 4);
+        assert_eq!(program.stmt_ids().count(), 2);
+        let Statement::Let(_, _, value) = program[program.statements[0]] else {
+            panic!("expected a let statement");
+        };
+        let Expr::Infix(left, Infix::Plus, right) = program[value] else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(program[left], Expr::Literal(Literal::Int(1)));
+        assert_eq!(program[right], Expr::Literal(Literal::Int(2)));
+        assert_ne!(NodeId::from(left), NodeId::from(right));
+    }
+    #[test]
+    fn test_spans() {
+        let input = "let x = (1 + 2) * -y;\nif (x) { x } else { 0 }";
+        let mut parser = Parser::new(Lexer::new(input).with_file(FileId(1)));
+        let program = parser.parse_program();
+        check_errors(parser);
+        let text = |id: NodeId| {
+            let span = program.span(id);
+            assert_eq!(span.file, FileId(1));
+            &input[span.start.offset..span.end.offset]
+        };
+        let let_stmt = program.statements[0];
+        assert_eq!(text(let_stmt.into()), "let x = (1 + 2) * -y;");
+        let Statement::Let(_, _, value) = program[let_stmt] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(text(value.into()), "(1 + 2) * -y");
+        let Expr::Infix(left, _, right) = program[value] else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(text(left.into()), "(1 + 2)");
+        assert_eq!(text(right.into()), "-y");
+        let if_stmt = program.statements[1];
+        assert_eq!(text(if_stmt.into()), "if (x) { x } else { 0 }");
+        assert_eq!(program.span(if_stmt).start.line, 2);
+        assert_eq!(program.span(if_stmt).end.column, 24);
     }
 }
@@ -0,0 +1,487 @@
+use std::fmt;
+
+use super::ast::*;
+
+// How many parentheses the printer emits around prefix and infix expressions
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Parens {
+    // Every prefix and infix expression is wrapped, like the book's `String()` methods
+    Full,
+    // Only where precedence or associativity requires them
+    Minimal,
+}
+
+// Prints a program, or a single node of it, back as source that parses to the same tree.
+// Literals no token can spell, negative numbers, NaN and infinities, are printed as an expression
+// computing them instead and parse back as that expression. String literals have no syntax yet.
+pub struct ProgramDisplay<'a> {
+    program: &'a Program,
+    node: Option<NodeId>,
+    parens: Parens,
+}
+
+impl Program {
+    pub fn display(&self, parens: Parens) -> ProgramDisplay<'_> {
+        ProgramDisplay {
+            program: self,
+            node: None,
+            parens,
+        }
+    }
+    pub fn display_node(&self, node: impl Into<NodeId>, parens: Parens) -> ProgramDisplay<'_> {
+        ProgramDisplay {
+            program: self,
+            node: Some(node.into()),
+            parens,
+        }
+    }
+}
+
+// Canonical fully parenthesized source, one top level statement per line
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(Parens::Full).fmt(f)
+    }
+}
+
+impl<'a> fmt::Display for ProgramDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.node {
+            Some(NodeId::Expr(id)) => self.expr(f, id, Precedence::Lowest),
+            Some(NodeId::Stmt(id)) => self.stmt(f, id),
+            None => {
+                for (i, id) in self.program.statements.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    self.stmt(f, *id)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a> ProgramDisplay<'a> {
+    fn stmt(&self, f: &mut fmt::Formatter, id: StmtId) -> fmt::Result {
+        match &self.program[id] {
+            Statement::Blank => Ok(()),
+            Statement::Let(name, annotation, value) => {
+                f.write_str(&let_str(self.program, name, annotation.as_ref()))?;
+                self.expr(f, *value, Precedence::Lowest)?;
+                write!(f, ";")
+            }
+            Statement::Return(Some(value)) => {
+                write!(f, "return ")?;
+                self.expr(f, *value, Precedence::Lowest)?;
+                write!(f, ";")
+            }
+            Statement::Return(None) => write!(f, "return;"),
+            Statement::Expr(expr) => {
+                self.expr(f, *expr, Precedence::Lowest)?;
+                write!(f, ";")
+            }
+        }
+    }
+    fn block(&self, f: &mut fmt::Formatter, block: &BlockStatement) -> fmt::Result {
+        if block.is_empty() {
+            return write!(f, "{{}}");
+        }
+        write!(f, "{{")?;
+        for id in block {
+            write!(f, " ")?;
+            self.stmt(f, *id)?;
+        }
+        write!(f, " }}")
+    }
+    // `context` is the lowest precedence that can appear here without parentheses
+    fn expr(&self, f: &mut fmt::Formatter, id: ExprId, context: Precedence) -> fmt::Result {
+        let precedence = match &self.program[id] {
+            Expr::Prefix(..) => Precedence::Prefix,
+            Expr::Infix(_, infix, _) => infix_precedence(infix),
+            Expr::Literal(literal) => match literal_precedence(literal) {
+                Some(precedence) => precedence,
+                None => return self.expr_unwrapped(f, id),
+            },
+            _ => return self.expr_unwrapped(f, id),
+        };
+        let wrap = self.parens == Parens::Full || context > precedence;
+        if wrap {
+            write!(f, "(")?;
+        }
+        self.expr_unwrapped(f, id)?;
+        if wrap {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+    // Prints the expression without parentheses around itself
+    fn expr_unwrapped(&self, f: &mut fmt::Formatter, id: ExprId) -> fmt::Result {
+        match &self.program[id] {
+            Expr::Ident(ident) => f.write_str(self.program.name(ident.0)),
+            Expr::Literal(literal) => write_literal(f, literal, self.parens),
+            Expr::Prefix(prefix, right) => {
+                write!(f, "{}", prefix_str(prefix))?;
+                self.expr(f, *right, Precedence::Prefix)
+            }
+            Expr::Infix(left, infix, right) => {
+                let precedence = infix_precedence(infix);
+                self.expr(f, *left, precedence)?;
+                write!(f, " {} ", infix_str(infix))?;
+                // Operators are left associative, so an equal precedence on the right needs parentheses
+                self.expr(f, *right, precedence.next())
+            }
+            // The parentheses around the condition are part of the syntax
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                write!(f, "if (")?;
+                self.expr_unwrapped(f, *cond)?;
+                write!(f, ") ")?;
+                self.block(f, consequence)?;
+                if let Some(alternative) = alternative {
+                    write!(f, " else ")?;
+                    self.block(f, alternative)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Precedence of the expression a literal is printed as, `None` when it is a single token
+fn literal_precedence(literal: &Literal) -> Option<Precedence> {
+    match literal {
+        Literal::Int(i64::MIN) => Some(Precedence::Sum),
+        Literal::Int(int) if *int < 0 => Some(Precedence::Prefix),
+        Literal::BigInt(int) if int.is_negative() => Some(Precedence::Prefix),
+        Literal::Float(float) if !float.is_finite() => Some(Precedence::Product),
+        Literal::Float(float) if float.is_sign_negative() => Some(Precedence::Prefix),
+        _ => None,
+    }
+}
+
+// Writes the literal without parentheses around itself, the ones inside follow `parens`
+fn write_literal(f: &mut fmt::Formatter, literal: &Literal, parens: Parens) -> fmt::Result {
+    // Wraps the negated operand of an infix expression when every prefix expression is wrapped
+    let negated = |f: &mut fmt::Formatter, text: &str| match parens {
+        Parens::Full => write!(f, "(-{})", text),
+        Parens::Minimal => write!(f, "-{}", text),
+    };
+    match literal {
+        // Its magnitude is one past the largest int literal
+        Literal::Int(i64::MIN) => {
+            negated(f, &i64::MAX.to_string())?;
+            f.write_str(" - 1")
+        }
+        Literal::Int(int) if *int < 0 => write!(f, "-{}", int.unsigned_abs()),
+        Literal::Int(int) => write!(f, "{}", int),
+        Literal::BigInt(int) if int.is_negative() => write!(f, "-{}n", -int.clone()),
+        Literal::BigInt(int) => write!(f, "{}n", int),
+        Literal::Float(float) if float.is_nan() => f.write_str("0.0 / 0.0"),
+        Literal::Float(float) if float.is_infinite() => {
+            if *float < 0.0 {
+                negated(f, "1e308")?;
+            } else {
+                f.write_str("1e308")?;
+            }
+            f.write_str(" * 10.0")
+        }
+        // Debug keeps a `.0` or exponent so the literal lexes as a float again
+        Literal::Float(float) if float.is_sign_negative() => write!(f, "-{:?}", -float),
+        Literal::Float(float) => write!(f, "{:?}", float),
+        Literal::String(string) => write!(f, "\"{}\"", string),
+        Literal::Boolean(boolean) => write!(f, "{}", boolean),
+    }
+}
+
+pub fn prefix_str(prefix: &Prefix) -> &'static str {
+    match prefix {
+        Prefix::Not => "!",
+        Prefix::Minus => "-",
+    }
+}
+pub fn infix_str(infix: &Infix) -> &'static str {
+    match infix {
+        Infix::Plus => "+",
+        Infix::Minus => "-",
+        Infix::Divide => "/",
+        Infix::Multiply => "*",
+        Infix::Equal => "==",
+        Infix::NotEqual => "!=",
+        Infix::GreaterThanEqual => ">=",
+        Infix::GreaterThan => ">",
+        Infix::LessThanEqual => "<=",
+        Infix::LessThan => "<",
+    }
+}
+pub fn infix_precedence(infix: &Infix) -> Precedence {
+    match infix {
+        Infix::Equal | Infix::NotEqual => Precedence::Equals,
+        Infix::LessThan | Infix::LessThanEqual | Infix::GreaterThan | Infix::GreaterThanEqual => {
+            Precedence::LessGreater
+        }
+        Infix::Plus | Infix::Minus => Precedence::Sum,
+        Infix::Multiply | Infix::Divide => Precedence::Product,
+    }
+}
+// Start of a `let` statement up to the value, e.g. `let x: int = `
+pub fn let_str(program: &Program, name: &Ident, annotation: Option<&TypeExpr>) -> String {
+    let name = program.name(name.0);
+    match annotation {
+        Some(annotation) => format!("let {}: {} = ", name, program.display_type(annotation)),
+        None => format!("let {} = ", name),
+    }
+}
+
+// Prints a type annotation as written in source
+pub struct TypeDisplay<'a> {
+    program: &'a Program,
+    annotation: &'a TypeExpr,
+}
+
+impl Program {
+    pub fn display_type<'a>(&'a self, annotation: &'a TypeExpr) -> TypeDisplay<'a> {
+        TypeDisplay {
+            program: self,
+            annotation,
+        }
+    }
+}
+
+impl<'a> fmt::Display for TypeDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let program = self.program;
+        match self.annotation {
+            TypeExpr::Named(name) => f.write_str(program.name(name.0)),
+            TypeExpr::Array(element) => write!(f, "[{}]", program.display_type(element)),
+            TypeExpr::Hash(key, value) => write!(
+                f,
+                "{{{}: {}}}",
+                program.display_type(key),
+                program.display_type(value)
+            ),
+            TypeExpr::Function(params, ret) => {
+                f.write_str("fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", program.display_type(param))?;
+                }
+                write!(f, ") -> {}", program.display_type(ret))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigint::BigInt;
+    use crate::parser::testing::{parse, Rng};
+    use crate::token::Span;
+
+    #[test]
+    fn test_print() {
+        let tests = [
+            (
+                "let x = 1 + 2 * 3;",
+                "let x = (1 + (2 * 3));",
+                "let x = 1 + 2 * 3;",
+            ),
+            ("(1 + 2) * 3", "((1 + 2) * 3);", "(1 + 2) * 3;"),
+            ("1 - (2 - 3)", "(1 - (2 - 3));", "1 - (2 - 3);"),
+            ("1 - 2 - 3", "((1 - 2) - 3);", "1 - 2 - 3;"),
+            ("-(a + b) * !c", "((-(a + b)) * (!c));", "-(a + b) * !c;"),
+            ("a < b == false", "((a < b) == false);", "a < b == false;"),
+            (
+                "if (x < y) { x } else { return y; }",
+                "if (x < y) { x; } else { return y; };",
+                "if (x < y) { x; } else { return y; };",
+            ),
+            ("if (true) {}", "if (true) {};", "if (true) {};"),
+            ("return;", "return;", "return;"),
+            (
+                "2.5 * 1e-9 + 10n",
+                "((2.5 * 1e-9) + 10n);",
+                "2.5 * 1e-9 + 10n;",
+            ),
+        ];
+        for (input, full, minimal) in tests {
+            let program = parse(input);
+            assert_eq!(program.to_string(), full);
+            assert_eq!(program.display(Parens::Minimal).to_string(), minimal);
+        }
+    }
+    #[test]
+    fn test_print_node() {
+        let program = parse("let x = -a * b;");
+        let Statement::Let(_, _, value) = program[program.statements[0]] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(
+            program.display_node(value, Parens::Full).to_string(),
+            "((-a) * b)"
+        );
+        assert_eq!(
+            program
+                .display_node(program.statements[0], Parens::Minimal)
+                .to_string(),
+            "let x = -a * b;"
+        );
+    }
+
+    #[test]
+    fn test_print_literal_without_token() {
+        let tests = [
+            (Literal::Int(-5), "(-5);", "-5;"),
+            (
+                Literal::Int(i64::MIN),
+                "((-9223372036854775807) - 1);",
+                "-9223372036854775807 - 1;",
+            ),
+            (Literal::BigInt(BigInt::from(-5)), "(-5n);", "-5n;"),
+            (Literal::Float(-0.0), "(-0.0);", "-0.0;"),
+            (Literal::Float(f64::NAN), "(0.0 / 0.0);", "0.0 / 0.0;"),
+            (
+                Literal::Float(f64::INFINITY),
+                "(1e308 * 10.0);",
+                "1e308 * 10.0;",
+            ),
+            (
+                Literal::Float(f64::NEG_INFINITY),
+                "((-1e308) * 10.0);",
+                "-1e308 * 10.0;",
+            ),
+            (
+                Literal::String("a\nb".to_string()),
+                "\"a\nb\";",
+                "\"a\nb\";",
+            ),
+        ];
+        for (literal, full, minimal) in tests {
+            let mut program = Program::new();
+            let expr = program.alloc_expr(Expr::Literal(literal), Span::default());
+            let stmt = program.alloc_stmt(Statement::Expr(expr), Span::default());
+            program.statements.push(stmt);
+            assert_eq!(program.to_string(), full);
+            assert_eq!(program.display(Parens::Minimal).to_string(), minimal);
+        }
+        // Wrapped like the expression it is printed as
+        let mut program = parse("-a * b");
+        let Statement::Expr(id) = program[program.statements[0]] else {
+            panic!("expected an expression statement");
+        };
+        let Expr::Infix(left, ..) = program[id] else {
+            panic!("expected an infix expression");
+        };
+        program[left] = Expr::Literal(Literal::Int(i64::MIN));
+        assert_eq!(
+            program.display(Parens::Minimal).to_string(),
+            "(-9223372036854775807 - 1) * b;"
+        );
+    }
+
+    fn gen_expr(rng: &mut Rng, program: &mut Program, depth: u32) -> ExprId {
+        let leaf = depth == 0 || rng.below(3) == 0;
+        let expr = match if leaf { rng.below(2) } else { 2 + rng.below(3) } {
+            0 => Expr::Ident(Ident(program.intern(rng.pick(&["a", "b", "foo", "é"])))),
+            1 => Expr::Literal(rng.pick(&[
+                Literal::Int(0),
+                Literal::Int(42),
+                Literal::Int(i64::MAX),
+                Literal::BigInt(BigInt::from(i64::MAX) * 10),
+                Literal::Float(0.5),
+                Literal::Float(1e-9),
+                Literal::Float(2.5e300),
+                Literal::Int(-7),
+                Literal::Int(i64::MIN),
+                Literal::BigInt(BigInt::from(i64::MIN) * 10),
+                Literal::Float(-0.5),
+                Literal::Float(-0.0),
+                Literal::Float(f64::NAN),
+                Literal::Float(f64::INFINITY),
+                Literal::Float(f64::NEG_INFINITY),
+                Literal::Boolean(true),
+                Literal::Boolean(false),
+            ])),
+            2 => {
+                let prefix = rng.pick(&[Prefix::Not, Prefix::Minus]);
+                Expr::Prefix(prefix, gen_expr(rng, program, depth - 1))
+            }
+            3 => {
+                let left = gen_expr(rng, program, depth - 1);
+                let infix = rng.pick(&[
+                    Infix::Plus,
+                    Infix::Minus,
+                    Infix::Multiply,
+                    Infix::Divide,
+                    Infix::Equal,
+                    Infix::NotEqual,
+                    Infix::LessThan,
+                    Infix::GreaterThan,
+                ]);
+                Expr::Infix(left, infix, gen_expr(rng, program, depth - 1))
+            }
+            _ => {
+                let cond = gen_expr(rng, program, depth - 1);
+                let consequence = gen_block(rng, program, depth - 1);
+                let alternative = match rng.below(2) {
+                    0 => None,
+                    _ => Some(gen_block(rng, program, depth - 1)),
+                };
+                Expr::If {
+                    cond,
+                    consequence,
+                    alternative,
+                }
+            }
+        };
+        program.alloc_expr(expr, Span::default())
+    }
+    fn gen_stmt(rng: &mut Rng, program: &mut Program, depth: u32) -> StmtId {
+        let stmt = match rng.below(4) {
+            0 => {
+                let name = Ident(program.intern("x"));
+                Statement::Let(name, None, gen_expr(rng, program, depth))
+            }
+            1 => Statement::Return(Some(gen_expr(rng, program, depth))),
+            2 => Statement::Return(None),
+            _ => Statement::Expr(gen_expr(rng, program, depth)),
+        };
+        program.alloc_stmt(stmt, Span::default())
+    }
+    fn gen_block(rng: &mut Rng, program: &mut Program, depth: u32) -> BlockStatement {
+        (0..rng.below(3))
+            .map(|_| gen_stmt(rng, program, depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_print_round_trip() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..500 {
+            let mut program = Program::new();
+            for _ in 0..1 + rng.below(3) {
+                let stmt = gen_stmt(&mut rng, &mut program, 4);
+                program.statements.push(stmt);
+            }
+            // Literals printed as an expression parse back as that expression
+            let computed = program.expr_ids().any(|id| match &program[id] {
+                Expr::Literal(literal) => literal_precedence(literal).is_some(),
+                _ => false,
+            });
+            for parens in [Parens::Full, Parens::Minimal] {
+                let source = program.display(parens).to_string();
+                let parsed = parse(&source);
+                assert_eq!(parsed.display(parens).to_string(), source);
+                if !computed {
+                    assert_eq!(parsed, program, "{}", source);
+                }
+            }
+        }
+    }
+}
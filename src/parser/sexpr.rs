@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use super::ast::*;
+use super::printer::{infix_str, prefix_str};
+
+impl Program {
+    // Fully parenthesized dump of the tree, one top level statement per line. Meant for
+    // diffing parser output, unlike `display` it does not read back as source.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::from("(program");
+        for id in &self.statements {
+            out.push_str("\n  ");
+            self.write_stmt_sexpr(&mut out, *id);
+        }
+        out.push(')');
+        out
+    }
+
+    fn write_stmt_sexpr(&self, out: &mut String, id: StmtId) {
+        match &self[id] {
+            Statement::Blank => out.push_str("(blank)"),
+            Statement::Let(name, None, value) => {
+                write!(out, "(let {} ", self.name(name.0)).unwrap();
+                self.write_expr_sexpr(out, *value);
+                out.push(')');
+            }
+            // Types are written as in source, `(let (: x [int]) y)`
+            Statement::Let(name, Some(annotation), value) => {
+                let annotation = self.display_type(annotation);
+                write!(out, "(let (: {} {}) ", self.name(name.0), annotation).unwrap();
+                self.write_expr_sexpr(out, *value);
+                out.push(')');
+            }
+            Statement::Return(Some(value)) => {
+                out.push_str("(return ");
+                self.write_expr_sexpr(out, *value);
+                out.push(')');
+            }
+            Statement::Return(None) => out.push_str("(return)"),
+            Statement::Expr(value) => self.write_expr_sexpr(out, *value),
+        }
+    }
+    fn write_block_sexpr(&self, out: &mut String, block: &BlockStatement) {
+        out.push_str("(block");
+        for id in block {
+            out.push(' ');
+            self.write_stmt_sexpr(out, *id);
+        }
+        out.push(')');
+    }
+    fn write_expr_sexpr(&self, out: &mut String, id: ExprId) {
+        match &self[id] {
+            Expr::Ident(ident) => out.push_str(self.name(ident.0)),
+            Expr::Literal(Literal::Int(int)) => write!(out, "{}", int).unwrap(),
+            Expr::Literal(Literal::BigInt(int)) => write!(out, "{}n", int).unwrap(),
+            Expr::Literal(Literal::Float(float)) => write!(out, "{:?}", float).unwrap(),
+            Expr::Literal(Literal::String(string)) => write!(out, "{:?}", string).unwrap(),
+            Expr::Literal(Literal::Boolean(boolean)) => write!(out, "{}", boolean).unwrap(),
+            Expr::Prefix(prefix, right) => {
+                write!(out, "({} ", prefix_str(prefix)).unwrap();
+                self.write_expr_sexpr(out, *right);
+                out.push(')');
+            }
+            Expr::Infix(left, infix, right) => {
+                write!(out, "({} ", infix_str(infix)).unwrap();
+                self.write_expr_sexpr(out, *left);
+                out.push(' ');
+                self.write_expr_sexpr(out, *right);
+                out.push(')');
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                out.push_str("(if ");
+                self.write_expr_sexpr(out, *cond);
+                out.push(' ');
+                self.write_block_sexpr(out, consequence);
+                if let Some(alternative) = alternative {
+                    out.push(' ');
+                    self.write_block_sexpr(out, alternative);
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::testing::parse;
+
+    #[test]
+    fn test_sexpr() {
+        let input =
+            "let x = 1 + 2 * -3; if (x < y) { x } else { return 10n; }\nreturn;\nlet y: [int] = x";
+        let program = parse(input);
+        let expected = r#"(program
+  (let x (+ 1 (* 2 (- 3))))
+  (if (< x y) (block x) (block (return 10n)))
+  (return)
+  (let (: y [int]) x))"#;
+        assert_eq!(program.to_sexpr(), expected);
+    }
+}
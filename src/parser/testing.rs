@@ -0,0 +1,27 @@
+// Helpers shared by the tests of the modules working on parsed programs
+use super::ast::Program;
+use super::Parser;
+use crate::lexer::Lexer;
+
+// Parses `input`, which the test expects to be free of errors
+pub fn parse(input: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    assert_eq!(parser.get_errors(), Vec::<String>::new(), "{}", input);
+    program
+}
+
+// Small xorshift generator, so generated tests are reproducible without extra dependencies
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+    pub fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize].clone()
+    }
+}
@@ -0,0 +1,475 @@
+// Traversals over the arena ast. Each trait method defaults to the matching `walk_*` function,
+// so an implementation only overrides the nodes it cares about and calls the walk function to
+// keep recursing. The walks match on every node kind without a catch-all arm, so adding a node
+// fails to compile here until its children are visited.
+use super::ast::*;
+
+// Read-only traversal
+pub trait Visitor {
+    fn visit_block(&mut self, program: &Program, block: &[StmtId]) {
+        walk_block(self, program, block)
+    }
+    fn visit_stmt(&mut self, program: &Program, id: StmtId) {
+        walk_stmt(self, program, id)
+    }
+    fn visit_expr(&mut self, program: &Program, id: ExprId) {
+        walk_expr(self, program, id)
+    }
+    // Annotation of the `let` statement `id`, or a type nested in it
+    fn visit_type(&mut self, program: &Program, id: StmtId, ty: &TypeExpr) {
+        walk_type(self, program, id, ty)
+    }
+    // Leaves, `id` is the node the ident is part of: a `let` statement or an ident expression
+    fn visit_ident(&mut self, _program: &Program, _id: NodeId, _ident: &Ident) {}
+    fn visit_literal(&mut self, _program: &Program, _id: ExprId, _literal: &Literal) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    visitor.visit_block(program, &program.statements)
+}
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, program: &Program, block: &[StmtId]) {
+    for id in block {
+        visitor.visit_stmt(program, *id);
+    }
+}
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, program: &Program, id: StmtId) {
+    match &program[id] {
+        Statement::Blank | Statement::Return(None) => {}
+        Statement::Let(name, annotation, value) => {
+            visitor.visit_ident(program, id.into(), name);
+            if let Some(annotation) = annotation {
+                visitor.visit_type(program, id, annotation);
+            }
+            visitor.visit_expr(program, *value);
+        }
+        Statement::Return(Some(value)) | Statement::Expr(value) => {
+            visitor.visit_expr(program, *value)
+        }
+    }
+}
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, program: &Program, id: ExprId) {
+    match &program[id] {
+        Expr::Ident(ident) => visitor.visit_ident(program, id.into(), ident),
+        Expr::Literal(literal) => visitor.visit_literal(program, id, literal),
+        Expr::Prefix(_, right) => visitor.visit_expr(program, *right),
+        Expr::Infix(left, _, right) => {
+            visitor.visit_expr(program, *left);
+            visitor.visit_expr(program, *right);
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr(program, *cond);
+            visitor.visit_block(program, consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block(program, alternative);
+            }
+        }
+    }
+}
+
+// Type names are not visited as idents, they do not refer to `let`s
+pub fn walk_type<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    program: &Program,
+    id: StmtId,
+    ty: &TypeExpr,
+) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Array(element) => visitor.visit_type(program, id, element),
+        TypeExpr::Hash(key, value) => {
+            visitor.visit_type(program, id, key);
+            visitor.visit_type(program, id, value);
+        }
+        TypeExpr::Function(params, ret) => {
+            for param in params {
+                visitor.visit_type(program, id, param);
+            }
+            visitor.visit_type(program, id, ret);
+        }
+    }
+}
+
+// Traversal that may rewrite nodes in place through `program[id]`. Ids keep pointing at the
+// same slots, so side tables keyed by id describe the rewritten nodes afterwards.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, program: &mut Program, block: &[StmtId]) {
+        walk_block_mut(self, program, block)
+    }
+    fn visit_stmt_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_stmt_mut(self, program, id)
+    }
+    fn visit_expr_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_expr_mut(self, program, id)
+    }
+    // Annotation of the `let` statement `id`, written back into it once the visit returns
+    fn visit_type_mut(&mut self, program: &mut Program, id: StmtId, ty: &mut TypeExpr) {
+        walk_type_mut(self, program, id, ty)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    let statements = program.statements.clone();
+    visitor.visit_block_mut(program, &statements)
+}
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program,
+    block: &[StmtId],
+) {
+    for id in block {
+        visitor.visit_stmt_mut(program, *id);
+    }
+}
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    match program[id].clone() {
+        Statement::Blank | Statement::Return(None) => {}
+        Statement::Let(_, annotation, value) => {
+            if let Some(mut annotation) = annotation {
+                visitor.visit_type_mut(program, id, &mut annotation);
+                if let Statement::Let(_, slot, _) = &mut program[id] {
+                    *slot = Some(annotation);
+                }
+            }
+            visitor.visit_expr_mut(program, value)
+        }
+        Statement::Return(Some(value)) | Statement::Expr(value) => {
+            visitor.visit_expr_mut(program, value)
+        }
+    }
+}
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program, id: ExprId) {
+    // Children are copied out first, the visitor needs the program mutably to recurse
+    match program[id].clone() {
+        Expr::Ident(_) | Expr::Literal(_) => {}
+        Expr::Prefix(_, right) => visitor.visit_expr_mut(program, right),
+        Expr::Infix(left, _, right) => {
+            visitor.visit_expr_mut(program, left);
+            visitor.visit_expr_mut(program, right);
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr_mut(program, cond);
+            visitor.visit_block_mut(program, &consequence);
+            if let Some(alternative) = alternative {
+                visitor.visit_block_mut(program, &alternative);
+            }
+        }
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program,
+    id: StmtId,
+    ty: &mut TypeExpr,
+) {
+    match ty {
+        TypeExpr::Named(_) => {}
+        TypeExpr::Array(element) => visitor.visit_type_mut(program, id, element),
+        TypeExpr::Hash(key, value) => {
+            visitor.visit_type_mut(program, id, key);
+            visitor.visit_type_mut(program, id, value);
+        }
+        TypeExpr::Function(params, ret) => {
+            for param in params {
+                visitor.visit_type_mut(program, id, param);
+            }
+            visitor.visit_type_mut(program, id, ret);
+        }
+    }
+}
+
+// Tree rewriting pass. Instead of changing nodes in place a fold returns the id of the
+// replacement, allocated in the same arena, and leaves the original nodes untouched, so
+// results computed for the old tree stay valid. Returning `None` from `fold_stmt` drops
+// the statement. Replaced nodes stay in the arena unreferenced.
+pub trait Fold {
+    fn fold_block(&mut self, program: &mut Program, block: BlockStatement) -> BlockStatement {
+        fold_block_children(self, program, block)
+    }
+    fn fold_stmt(&mut self, program: &mut Program, id: StmtId) -> Option<StmtId> {
+        Some(fold_stmt_children(self, program, id))
+    }
+    fn fold_expr(&mut self, program: &mut Program, id: ExprId) -> ExprId {
+        fold_expr_children(self, program, id)
+    }
+    // Types are not in the arena, a fold returns the replacement itself
+    fn fold_type(&mut self, program: &mut Program, ty: TypeExpr) -> TypeExpr {
+        fold_type_children(self, program, ty)
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, mut program: Program) -> Program {
+    let statements = std::mem::take(&mut program.statements);
+    program.statements = folder.fold_block(&mut program, statements);
+    program
+}
+pub fn fold_block_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    program: &mut Program,
+    block: BlockStatement,
+) -> BlockStatement {
+    block
+        .into_iter()
+        .filter_map(|id| folder.fold_stmt(program, id))
+        .collect()
+}
+// Folds the children of a statement, allocating a new statement only if one of them changed
+pub fn fold_stmt_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    program: &mut Program,
+    id: StmtId,
+) -> StmtId {
+    let stmt = match program[id].clone() {
+        Statement::Blank | Statement::Return(None) => return id,
+        Statement::Let(name, annotation, value) => {
+            let folded_annotation = annotation
+                .clone()
+                .map(|annotation| folder.fold_type(program, annotation));
+            let folded = folder.fold_expr(program, value);
+            if folded == value && folded_annotation == annotation {
+                return id;
+            }
+            Statement::Let(name, folded_annotation, folded)
+        }
+        Statement::Return(Some(value)) => {
+            let folded = folder.fold_expr(program, value);
+            if folded == value {
+                return id;
+            }
+            Statement::Return(Some(folded))
+        }
+        Statement::Expr(value) => {
+            let folded = folder.fold_expr(program, value);
+            if folded == value {
+                return id;
+            }
+            Statement::Expr(folded)
+        }
+    };
+    let span = program.span(id);
+    program.alloc_stmt(stmt, span)
+}
+// Folds the children of an expression, allocating a new expression only if one of them changed
+pub fn fold_expr_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    program: &mut Program,
+    id: ExprId,
+) -> ExprId {
+    let expr = match program[id].clone() {
+        Expr::Ident(_) | Expr::Literal(_) => return id,
+        Expr::Prefix(prefix, right) => {
+            let folded = folder.fold_expr(program, right);
+            if folded == right {
+                return id;
+            }
+            Expr::Prefix(prefix, folded)
+        }
+        Expr::Infix(left, infix, right) => {
+            let folded_left = folder.fold_expr(program, left);
+            let folded_right = folder.fold_expr(program, right);
+            if folded_left == left && folded_right == right {
+                return id;
+            }
+            Expr::Infix(folded_left, infix, folded_right)
+        }
+        Expr::If {
+            cond,
+            consequence,
+            alternative,
+        } => {
+            let folded_cond = folder.fold_expr(program, cond);
+            let folded_consequence = folder.fold_block(program, consequence.clone());
+            let folded_alternative = alternative
+                .clone()
+                .map(|alternative| folder.fold_block(program, alternative));
+            if folded_cond == cond
+                && folded_consequence == consequence
+                && folded_alternative == alternative
+            {
+                return id;
+            }
+            Expr::If {
+                cond: folded_cond,
+                consequence: folded_consequence,
+                alternative: folded_alternative,
+            }
+        }
+    };
+    let span = program.span(id);
+    program.alloc_expr(expr, span)
+}
+pub fn fold_type_children<F: Fold + ?Sized>(
+    folder: &mut F,
+    program: &mut Program,
+    ty: TypeExpr,
+) -> TypeExpr {
+    match ty {
+        TypeExpr::Named(_) => ty,
+        TypeExpr::Array(element) => TypeExpr::Array(Box::new(folder.fold_type(program, *element))),
+        TypeExpr::Hash(key, value) => TypeExpr::Hash(
+            Box::new(folder.fold_type(program, *key)),
+            Box::new(folder.fold_type(program, *value)),
+        ),
+        TypeExpr::Function(params, ret) => TypeExpr::Function(
+            params
+                .into_iter()
+                .map(|param| folder.fold_type(program, param))
+                .collect(),
+            Box::new(folder.fold_type(program, *ret)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::printer::Parens;
+    use crate::parser::testing::parse;
+
+    #[test]
+    fn test_visitor() {
+        #[derive(Default)]
+        struct Names(Vec<String>, usize);
+        impl Visitor for Names {
+            fn visit_ident(&mut self, program: &Program, _id: NodeId, ident: &Ident) {
+                self.0.push(program.name(ident.0).to_string());
+            }
+            fn visit_literal(&mut self, _program: &Program, _id: ExprId, _literal: &Literal) {
+                self.1 += 1;
+            }
+        }
+        let program = parse("let a = 1 + b; if (!c) { return d * 2; } else { e }");
+        let mut names = Names::default();
+        walk_program(&mut names, &program);
+        assert_eq!(names.0, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(names.1, 2);
+    }
+    #[test]
+    fn test_visitor_mut() {
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_expr_mut(&mut self, program: &mut Program, id: ExprId) {
+                if let Expr::Ident(Ident(name)) = program[id] {
+                    let upper = program.name(name).to_uppercase();
+                    program[id] = Expr::Ident(Ident(program.intern(&upper)));
+                }
+                walk_expr_mut(self, program, id)
+            }
+        }
+        let mut program = parse("let a = b; if (c) { -d } else { e + 1 }");
+        walk_program_mut(&mut Rename, &mut program);
+        assert_eq!(
+            program.display(Parens::Minimal).to_string(),
+            "let a = B;\nif (C) { -D; } else { E + 1; };"
+        );
+    }
+    #[test]
+    fn test_fold() {
+        // Drops `x + 0` down to `x` and removes bare identifier statements
+        struct Simplify;
+        impl Fold for Simplify {
+            fn fold_stmt(&mut self, program: &mut Program, id: StmtId) -> Option<StmtId> {
+                let id = fold_stmt_children(self, program, id);
+                match program[id] {
+                    Statement::Expr(value) if matches!(program[value], Expr::Ident(_)) => None,
+                    _ => Some(id),
+                }
+            }
+            fn fold_expr(&mut self, program: &mut Program, id: ExprId) -> ExprId {
+                let id = fold_expr_children(self, program, id);
+                match program[id] {
+                    Expr::Infix(left, Infix::Plus, right)
+                        if program[right] == Expr::Literal(Literal::Int(0)) =>
+                    {
+                        left
+                    }
+                    _ => id,
+                }
+            }
+        }
+        let program = parse("let a = b + 0 + 0; c + 0; if (d) { e; f * 2 }");
+        let original = program.clone();
+        let first = program.statements[0];
+        let folded = fold_program(&mut Simplify, program);
+        assert_eq!(
+            folded.display(Parens::Minimal).to_string(),
+            "let a = b;\nif (d) { f * 2; };"
+        );
+        // The original nodes are left as they were
+        assert!(folded.stmt_eq(first, &original, first));
+        assert_ne!(folded.statements[0], first);
+    }
+    #[test]
+    fn test_types() {
+        // Type names in annotations, each with the `let` it belongs to
+        #[derive(Default)]
+        struct TypeNames(Vec<(String, StmtId)>);
+        impl Visitor for TypeNames {
+            fn visit_type(&mut self, program: &Program, id: StmtId, ty: &TypeExpr) {
+                if let TypeExpr::Named(Ident(name)) = ty {
+                    self.0.push((program.name(*name).to_string(), id));
+                }
+                walk_type(self, program, id, ty)
+            }
+        }
+        let input = "let a: [int] = b; let f: fn(int, {string: bool}) -> any = g; let c = 1;";
+        let program = parse(input);
+        let mut names = TypeNames::default();
+        walk_program(&mut names, &program);
+        let (first, second) = (program.statements[0], program.statements[1]);
+        let expected = [
+            ("int", first),
+            ("int", second),
+            ("string", second),
+            ("bool", second),
+            ("any", second),
+        ];
+        let expected: Vec<(String, StmtId)> = expected
+            .into_iter()
+            .map(|(name, id)| (name.to_string(), id))
+            .collect();
+        assert_eq!(names.0, expected);
+
+        // Widens `int` to `float`, in place and as a fold
+        fn widen(program: &mut Program, ty: &mut TypeExpr) {
+            if let TypeExpr::Named(Ident(name)) = ty {
+                if program.name(*name) == "int" {
+                    *ty = TypeExpr::Named(Ident(program.intern("float")));
+                }
+            }
+        }
+        struct Widen;
+        impl VisitorMut for Widen {
+            fn visit_type_mut(&mut self, program: &mut Program, id: StmtId, ty: &mut TypeExpr) {
+                widen(program, ty);
+                walk_type_mut(self, program, id, ty)
+            }
+        }
+        impl Fold for Widen {
+            fn fold_type(&mut self, program: &mut Program, ty: TypeExpr) -> TypeExpr {
+                let mut ty = fold_type_children(self, program, ty);
+                widen(program, &mut ty);
+                ty
+            }
+        }
+        let expected =
+            "let a: [float] = b;\nlet f: fn(float, {string: bool}) -> any = g;\nlet c = 1;";
+        let mut visited = program.clone();
+        walk_program_mut(&mut Widen, &mut visited);
+        assert_eq!(visited.display(Parens::Minimal).to_string(), expected);
+        let third = program.statements[2];
+        let folded = fold_program(&mut Widen, program);
+        assert_eq!(folded.display(Parens::Minimal).to_string(), expected);
+        // Only the statements whose annotation changed are replaced
+        assert_ne!(folded.statements[0], first);
+        assert_eq!(folded.statements[2], third);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+use crate::builtins::Builtin;
+use crate::diagnostics::Diagnostic;
+use crate::parser::ast::*;
+use crate::parser::visit::{walk_expr, walk_program, walk_stmt, Visitor};
+use crate::suggest::closest;
+use crate::symbol::Symbol;
+use crate::token::{Span, KEYWORDS};
+
+// Where an identifier's value lives. `depth` counts the scopes between the use and the
+// declaring one, 0 being the innermost, and `slot` is the name's index in that scope.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Binding {
+    Local { depth: usize, slot: usize },
+    Builtin(Builtin),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ResolveError {
+    Undeclared {
+        name: String,
+        span: Span,
+        // A name in scope, builtin or keyword that is spelled almost the same
+        suggestion: Option<String>,
+    },
+}
+
+impl ResolveError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            ResolveError::Undeclared {
+                name,
+                span,
+                suggestion,
+            } => {
+                let diagnostic = Diagnostic::error(format!("`{}` is not declared", name), *span);
+                match suggestion {
+                    Some(suggestion) => {
+                        diagnostic.with_help(format!("did you mean `{}`?", suggestion))
+                    }
+                    None => diagnostic,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::Undeclared {
+                name,
+                span,
+                suggestion,
+            } => {
+                write!(f, "Identifier {} at {} is not declared", name, span.start)?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ", did you mean `{}`?", suggestion),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// Side tables filled in by `resolve`, keyed by the ids of the nodes they describe
+#[derive(Debug, Default)]
+pub struct Resolution {
+    // Binding of every identifier expression that resolved
+    pub uses: HashMap<ExprId, Binding>,
+    // Slot each `let` declares in its scope
+    pub declarations: HashMap<StmtId, usize>,
+    // The `let`s a local identifier may refer to, more than one after an `if` whose branches
+    // declare the name differently
+    pub definitions: HashMap<ExprId, Vec<StmtId>>,
+    pub errors: Vec<ResolveError>,
+}
+
+impl Resolution {
+    pub fn get(&self, id: ExprId) -> Option<Binding> {
+        self.uses.get(&id).copied()
+    }
+}
+
+// Resolves every identifier in the program against the `let`s before it. Block statements run in
+// the environment around them, so a `let` in an `if` branch stays visible after the `if`, but
+// not in the other branch. Like an environment, a scope has one slot per name it declares.
+pub fn resolve(program: &Program) -> Resolution {
+    let mut resolver = Resolver {
+        scopes: vec![Scope::default()],
+        resolution: Resolution::default(),
+    };
+    walk_program(&mut resolver, program);
+    resolver.resolution
+}
+
+#[derive(Default, Clone)]
+struct Scope {
+    // Slot of every name declared in the scope so far, in any branch
+    slots: HashMap<Symbol, usize>,
+    // The `let`s each visible name may have been bound by at this point
+    reaching: HashMap<Symbol, Vec<StmtId>>,
+}
+
+struct Resolver {
+    // Innermost scope last
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+}
+
+impl Resolver {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("no scope open")
+    }
+    fn lookup(&self, program: &Program, name: Symbol) -> Option<(Binding, Vec<StmtId>)> {
+        let local = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let lets = scope.reaching.get(&name)?;
+                let slot = scope.slots[&name];
+                Some((Binding::Local { depth, slot }, lets.clone()))
+            });
+        local.or_else(|| {
+            Builtin::from_name(program.name(name))
+                .map(|builtin| (Binding::Builtin(builtin), vec![]))
+        })
+    }
+    // Closest visible name to `name`, inner scopes first, then builtins and keywords
+    fn suggest(&self, program: &Program, name: Symbol) -> Option<String> {
+        let mut locals: Vec<&str> = vec![];
+        for scope in self.scopes.iter().rev() {
+            let mut names: Vec<&str> = scope
+                .reaching
+                .keys()
+                .map(|name| program.name(*name))
+                .collect();
+            names.sort_unstable();
+            locals.extend(names);
+        }
+        let candidates = locals
+            .into_iter()
+            .chain(Builtin::ALL.iter().map(|builtin| builtin.name()));
+        let candidates = candidates.chain(KEYWORDS.iter().copied());
+        closest(program.name(name), candidates).map(String::from)
+    }
+    // Resolves one branch of an `if` starting from the names visible before it, and returns
+    // the names visible after it
+    fn branch(
+        &mut self,
+        program: &Program,
+        block: &[StmtId],
+        before: &HashMap<Symbol, Vec<StmtId>>,
+    ) -> HashMap<Symbol, Vec<StmtId>> {
+        self.scope().reaching = before.clone();
+        self.visit_block(program, block);
+        mem::take(&mut self.scope().reaching)
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_stmt(&mut self, program: &Program, id: StmtId) {
+        let Statement::Let(name, _, value) = &program[id] else {
+            return walk_stmt(self, program, id);
+        };
+        // The value is resolved first, `let x = x;` refers to the previous `x`
+        self.visit_expr(program, *value);
+        let scope = self.scope();
+        let next = scope.slots.len();
+        let slot = *scope.slots.entry(name.0).or_insert(next);
+        scope.reaching.insert(name.0, vec![id]);
+        self.resolution.declarations.insert(id, slot);
+    }
+    fn visit_expr(&mut self, program: &Program, id: ExprId) {
+        match &program[id] {
+            Expr::Ident(Ident(name)) => match self.lookup(program, *name) {
+                Some((binding, definitions)) => {
+                    self.resolution.uses.insert(id, binding);
+                    if !definitions.is_empty() {
+                        self.resolution.definitions.insert(id, definitions);
+                    }
+                }
+                None => {
+                    let suggestion = self.suggest(program, *name);
+                    self.resolution.errors.push(ResolveError::Undeclared {
+                        name: program.name(*name).to_string(),
+                        span: program.span(id),
+                        suggestion,
+                    })
+                }
+            },
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.visit_expr(program, *cond);
+                let before = self.scope().reaching.clone();
+                let mut after = self.branch(program, consequence, &before);
+                let other = match alternative {
+                    Some(alternative) => self.branch(program, alternative, &before),
+                    None => before,
+                };
+                // A name either branch binds may come from either one afterwards
+                for (name, lets) in other {
+                    let merged = after.entry(name).or_default();
+                    for id in lets {
+                        if !merged.contains(&id) {
+                            merged.push(id);
+                        }
+                    }
+                }
+                self.scope().reaching = after;
+            }
+            _ => walk_expr(self, program, id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::testing::parse;
+    use crate::token::Position;
+
+    // Bindings of the identifier expressions, in source order
+    fn bindings(program: &Program, resolution: &Resolution) -> Vec<(String, Option<Binding>)> {
+        let mut idents: Vec<ExprId> = program
+            .expr_ids()
+            .filter(|id| matches!(program[*id], Expr::Ident(_)))
+            .collect();
+        idents.sort_by_key(|id| program.span(*id).start.offset);
+        idents
+            .into_iter()
+            .map(|id| match &program[id] {
+                Expr::Ident(ident) => (program.name(ident.0).to_string(), resolution.get(id)),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let program =
+            parse("let a = 1; let b = a; if (b) { let c = a + b; let a = c; a } else { len }; a");
+        let resolution = resolve(&program);
+        assert_eq!(resolution.errors, vec![]);
+        let local = |depth, slot| Some(Binding::Local { depth, slot });
+        assert_eq!(
+            bindings(&program, &resolution),
+            vec![
+                ("a".to_string(), local(0, 0)),
+                ("b".to_string(), local(0, 1)),
+                ("a".to_string(), local(0, 0)),
+                ("b".to_string(), local(0, 1)),
+                ("c".to_string(), local(0, 2)),
+                ("a".to_string(), local(0, 0)),
+                ("len".to_string(), Some(Binding::Builtin(Builtin::Len))),
+                ("a".to_string(), local(0, 0)),
+            ]
+        );
+        // The second `a` takes the slot of the first
+        let mut slots: Vec<usize> = resolution.declarations.values().copied().collect();
+        slots.sort();
+        assert_eq!(slots, vec![0, 0, 1, 2]);
+        // Every local use points at the `let`s declaring it, the last `a` at both
+        assert_eq!(resolution.definitions.len(), 7);
+        for (use_id, let_ids) in &resolution.definitions {
+            for let_id in let_ids {
+                let (Expr::Ident(used), Statement::Let(declared, _, _)) =
+                    (&program[*use_id], &program[*let_id])
+                else {
+                    panic!("expected an identifier and a let");
+                };
+                assert_eq!(used, declared);
+            }
+        }
+        let counts: Vec<usize> = program
+            .expr_ids()
+            .filter_map(|id| resolution.definitions.get(&id).map(Vec::len))
+            .collect();
+        assert_eq!(counts.iter().filter(|count| **count == 2).count(), 1);
+    }
+    #[test]
+    fn test_undeclared() {
+        // `y` is visible after the `if` but not in the other branch
+        let program = parse("let x = x;\nif (true) { let y = 1; } else { y }\ny + z;");
+        let resolution = resolve(&program);
+        let undeclared = |name, line, column, offset| ResolveError::Undeclared {
+            name: String::from(name),
+            span: Span {
+                start: Position {
+                    offset,
+                    line,
+                    column,
+                },
+                end: Position {
+                    offset: offset + 1,
+                    line,
+                    column: column + 1,
+                },
+                ..Span::default()
+            },
+            suggestion: None,
+        };
+        assert_eq!(
+            resolution.errors,
+            vec![
+                undeclared("x", 1, 9, 8),
+                undeclared("y", 2, 33, 43),
+                undeclared("z", 3, 5, 51),
+            ]
+        );
+        assert_eq!(
+            resolution.errors[0].to_string(),
+            "Identifier x at 1:9 is not declared"
+        );
+    }
+    #[test]
+    fn test_suggestions() {
+        let program =
+            parse("let count = 1; if (true) { let total = cout; lenn(totl) }; retrun fun");
+        let suggestions: Vec<String> = resolve(&program)
+            .errors
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(
+            suggestions,
+            vec![
+                "Identifier cout at 1:40 is not declared, did you mean `count`?",
+                "Identifier lenn at 1:46 is not declared, did you mean `len`?",
+                "Identifier totl at 1:50 is not declared, did you mean `total`?",
+                "Identifier retrun at 1:60 is not declared, did you mean `return`?",
+                "Identifier fun at 1:67 is not declared, did you mean `fn`?",
+            ]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+// Number of single character insertions, deletions, substitutions and swaps of adjacent
+// characters needed to turn `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows of the distance table for the prefixes of `a` two, one and zero characters shorter
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+// The candidate closest to `name`, if any is close enough to be a likely typo. Ties go to the
+// earliest candidate, so callers list the more likely ones first.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let len = name.chars().count();
+    // A third of the name may be wrong, but never all of it
+    let limit = (len / 3).max(1).min(len.saturating_sub(1));
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = edit_distance(name, candidate);
+        if distance <= limit && best.is_none_or(|(best, _)| distance < best) {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        let tests = [
+            ("", "", 0),
+            ("", "abc", 3),
+            ("kitten", "sitting", 3),
+            ("retrun", "return", 1),
+            ("fun", "fn", 1),
+            ("lenght", "length", 1),
+            ("café", "cafe", 1),
+        ];
+        for (a, b, expected) in tests {
+            assert_eq!(edit_distance(a, b), expected, "{} {}", a, b);
+            assert_eq!(edit_distance(b, a), expected, "{} {}", b, a);
+        }
+    }
+    #[test]
+    fn test_closest() {
+        let keywords = ["fn", "let", "true", "false", "if", "else", "return"];
+        assert_eq!(closest("retrun", keywords), Some("return"));
+        assert_eq!(closest("fun", keywords), Some("fn"));
+        assert_eq!(closest("esle", keywords), Some("else"));
+        assert_eq!(closest("ture", keywords), Some("true"));
+        // Too short to tell a typo from a different name
+        assert_eq!(closest("x", ["y"]), None);
+        assert_eq!(closest("count", keywords), None);
+        assert_eq!(closest("first", ["frist", "fist"]), Some("frist"));
+    }
+}
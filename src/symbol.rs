@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fmt, ops::Index};
+
+// Interned identifier, standing for a name in the `Interner` of the program it was parsed into.
+// Comparing and hashing a symbol never touches the string.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord, Debug)]
+pub struct Symbol(u32);
+
+// Names of the symbols of one program, freed along with it
+#[derive(Clone, Default)]
+pub struct Interner {
+    symbols: HashMap<Box<str>, Symbol>,
+    names: Vec<Box<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(Box::from(name));
+        self.symbols.insert(Box::from(name), symbol);
+        symbol
+    }
+    // Symbol of `name` if it was interned, without adding it
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+    pub fn contains(&self, symbol: Symbol) -> bool {
+        (symbol.0 as usize) < self.names.len()
+    }
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Index<Symbol> for Interner {
+    type Output = str;
+
+    fn index(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.names).finish()
+    }
+}
+
+// Serialized as the list of names, a symbol is its index in the list
+#[cfg(feature = "serde")]
+impl serde::Serialize for Interner {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.names)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Interner {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        let mut interner = Interner::new();
+        for name in names {
+            // Two symbols for one name would compare unequal
+            if interner.get(&name).is_some() {
+                let msg = format!("the name {:?} is listed twice", name);
+                return Err(serde::de::Error::custom(msg));
+            }
+            interner.intern(&name);
+        }
+        Ok(interner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::new();
+        let foo = interner.intern("foo");
+        assert_eq!(foo, interner.intern(&String::from("foo")));
+        assert_ne!(foo, interner.intern("bar"));
+        assert_eq!(&interner[foo], "foo");
+        assert_eq!(interner.get("bar"), Some(Symbol(1)));
+        assert_eq!(interner.get("baz"), None);
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -1,10 +1,71 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::bigint::BigInt;
+
+// Location in the source, `offset` is in bytes while `column` counts chars
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// Identifies the source file a span belongs to, single inputs use the default file 0
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub struct FileId(pub u32);
+
+// Range of a token or node in the source, `end` is the position just past its last char
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    // Smallest span covering both, `self` has to start before `other` ends
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Debug, Clone)]
-pub enum Token {
+pub struct SpannedToken<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
+// Text of identifiers and comments borrows from the input, only tokens that outlive it own
+// their text
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Debug, Clone)]
+pub enum Token<'a> {
     Illegal,
     Eof,
+    // Only produced when the lexer is asked to keep comments
+    Comment(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'a, str>),
     // Identifiers + literals
-    Ident(String),
+    Ident(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'a, str>),
     Int(i64),
+    // Integer literal with the `n` suffix, e.g. `100n`
+    BigInt(BigInt),
+    Float(f64),
     Boolean(bool),
     // Operators
     Assign,
@@ -18,13 +79,18 @@ pub enum Token {
     Gt,
     Eq,
     NotEq,
+    // `->` before the return type of a function type
+    Arrow,
     // Delimiters
     Comma,
+    Colon,
     Semicolon,
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
     // Keywords
     Function,
     Let,
@@ -33,7 +99,60 @@ pub enum Token {
     Return,
 }
 
-pub fn get_keyword(word: &str) -> Token {
+// Words `get_keyword` does not turn into identifiers
+pub const KEYWORDS: &[&str] = &["fn", "let", "true", "false", "if", "else", "return"];
+
+impl Token<'_> {
+    // Copies borrowed text so the token no longer depends on the input
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Comment(text) => Token::Comment(Cow::Owned(text.into_owned())),
+            Token::Ident(name) => Token::Ident(Cow::Owned(name.into_owned())),
+            Token::Illegal => Token::Illegal,
+            Token::Eof => Token::Eof,
+            Token::Int(int) => Token::Int(int),
+            Token::BigInt(int) => Token::BigInt(int),
+            Token::Float(float) => Token::Float(float),
+            Token::Boolean(boolean) => Token::Boolean(boolean),
+            Token::Assign => Token::Assign,
+            Token::Plus => Token::Plus,
+            Token::Minus => Token::Minus,
+            Token::Bang => Token::Bang,
+            Token::Asterisk => Token::Asterisk,
+            Token::Slash => Token::Slash,
+            Token::Lt => Token::Lt,
+            Token::Gt => Token::Gt,
+            Token::Eq => Token::Eq,
+            Token::NotEq => Token::NotEq,
+            Token::Arrow => Token::Arrow,
+            Token::Comma => Token::Comma,
+            Token::Colon => Token::Colon,
+            Token::Semicolon => Token::Semicolon,
+            Token::Lparen => Token::Lparen,
+            Token::Rparen => Token::Rparen,
+            Token::Lbrace => Token::Lbrace,
+            Token::Rbrace => Token::Rbrace,
+            Token::Lbracket => Token::Lbracket,
+            Token::Rbracket => Token::Rbracket,
+            Token::Function => Token::Function,
+            Token::Let => Token::Let,
+            Token::If => Token::If,
+            Token::Else => Token::Else,
+            Token::Return => Token::Return,
+        }
+    }
+}
+
+impl SpannedToken<'_> {
+    pub fn into_owned(self) -> SpannedToken<'static> {
+        SpannedToken {
+            token: self.token.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+pub fn get_keyword(word: &str) -> Token<'_> {
     match word {
         "fn" => Token::Function,
         "let" => Token::Let,
@@ -42,6 +161,25 @@ pub fn get_keyword(word: &str) -> Token {
         "if" => Token::If,
         "else" => Token::Else,
         "return" => Token::Return,
-        _ => Token::Ident(String::from(word)),
+        _ => Token::Ident(Cow::Borrowed(word)),
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_json_round_trip() {
+        let tokens: Vec<SpannedToken> = Lexer::new("let x = 10n + 2.5; // c")
+            .with_comments()
+            .collect();
+        let json = serde_json::to_string(&tokens).unwrap();
+        let back: Vec<SpannedToken> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(back[1].token, Token::Ident(Cow::Borrowed("x"))));
+        assert_eq!(back, tokens);
+        let first = serde_json::to_string(&tokens[1].token).unwrap();
+        assert_eq!(first, r#"{"Ident":"x"}"#);
     }
 }
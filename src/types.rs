@@ -0,0 +1,725 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::printer::{infix_str, prefix_str};
+use crate::parser::{ParseErrors, Parser};
+use crate::resolver::{resolve, Resolution, ResolveError};
+use crate::suggest::closest;
+use crate::token::Span;
+
+// Int, big int and float are separate types. Arithmetic mixing int with float gives a float and
+// int with big int a big int, other mixes take an explicit conversion.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Type {
+    Int,
+    BigInt,
+    Float,
+    Bool,
+    String,
+    // Value of an `if` without `else` or of a block not ending in an expression
+    Null,
+    // Written as an annotation to opt out of checking, fits wherever any other type does
+    Any,
+    Array(Box<Type>),
+    Hash(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    // Not known yet, later uses may still pin it down
+    Var(usize),
+}
+
+// Names a `TypeExpr::Named` annotation can use
+pub const TYPE_NAMES: &[&str] = &["int", "bigint", "float", "bool", "string", "null", "any"];
+
+impl Type {
+    // Type an annotation stands for, or the first name in it that is not a type
+    pub fn from_annotation(program: &Program, annotation: &TypeExpr) -> Result<Type, String> {
+        let ty = match annotation {
+            TypeExpr::Named(Ident(name)) => match program.name(*name) {
+                "int" => Type::Int,
+                "bigint" => Type::BigInt,
+                "float" => Type::Float,
+                "bool" => Type::Bool,
+                "string" => Type::String,
+                "null" => Type::Null,
+                "any" => Type::Any,
+                name => return Err(String::from(name)),
+            },
+            TypeExpr::Array(element) => {
+                Type::Array(Box::new(Type::from_annotation(program, element)?))
+            }
+            TypeExpr::Hash(key, value) => Type::Hash(
+                Box::new(Type::from_annotation(program, key)?),
+                Box::new(Type::from_annotation(program, value)?),
+            ),
+            TypeExpr::Function(params, ret) => Type::Function(
+                params
+                    .iter()
+                    .map(|param| Type::from_annotation(program, param))
+                    .collect::<Result<_, _>>()?,
+                Box::new(Type::from_annotation(program, ret)?),
+            ),
+        };
+        Ok(ty)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("int"),
+            Type::BigInt => f.write_str("bigint"),
+            Type::Float => f.write_str("float"),
+            Type::Bool => f.write_str("bool"),
+            Type::String => f.write_str("string"),
+            Type::Null => f.write_str("null"),
+            Type::Any => f.write_str("any"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Hash(key, value) => write!(f, "{{{}: {}}}", key, value),
+            Type::Function(params, ret) => {
+                f.write_str("fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Type::Var(var) => write!(f, "t{}", var),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypeError {
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    Operand {
+        operator: &'static str,
+        found: Type,
+        span: Span,
+    },
+    // An annotation names a type that does not exist, `span` is the annotated `let`
+    UnknownType {
+        name: String,
+        span: Span,
+        suggestion: Option<String>,
+    },
+}
+
+impl TypeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::Mismatch { span, .. }
+            | TypeError::Operand { span, .. }
+            | TypeError::UnknownType { span, .. } => *span,
+        }
+    }
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            TypeError::Mismatch {
+                expected, found, ..
+            } => Diagnostic::error(
+                format!("expected {}, found {}", expected, found),
+                self.span(),
+            ),
+            TypeError::Operand {
+                operator, found, ..
+            } => Diagnostic::error(
+                format!("`{}` cannot be applied to {}", operator, found),
+                self.span(),
+            ),
+            TypeError::UnknownType {
+                name, suggestion, ..
+            } => {
+                let diagnostic = Diagnostic::error(format!("unknown type `{}`", name), self.span());
+                match suggestion {
+                    Some(suggestion) => {
+                        diagnostic.with_help(format!("did you mean `{}`?", suggestion))
+                    }
+                    None => diagnostic,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::Mismatch {
+                expected,
+                found,
+                span,
+            } => write!(
+                f,
+                "Type mismatch at {}: expected {}, found {}",
+                span.start, expected, found
+            ),
+            TypeError::Operand {
+                operator,
+                found,
+                span,
+            } => write!(
+                f,
+                "Operator {} at {} cannot be applied to {}",
+                operator, span.start, found
+            ),
+            TypeError::UnknownType {
+                name,
+                span,
+                suggestion,
+            } => {
+                write!(f, "Unknown type {} at {}", name, span.start)?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ", did you mean `{}`?", suggestion),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// Side tables filled in by `check`
+#[derive(Debug, Default)]
+pub struct TypeCheck {
+    // Type of every expression, type variables left are the ones nothing constrained
+    pub types: HashMap<ExprId, Type>,
+    pub errors: Vec<TypeError>,
+}
+
+impl TypeCheck {
+    pub fn get(&self, id: ExprId) -> Option<&Type> {
+        self.types.get(&id)
+    }
+}
+
+// Infers the type of every expression with Hindley-Milner, `resolution` tells which `let` an
+// identifier refers to. Checking is optional and does not change what a program means:
+// `if` conditions and `!` take any type, and builtins are left unconstrained. Typing is
+// gradual, a `let` annotated `any` opts its uses out of checking.
+pub fn check(program: &Program, resolution: &Resolution) -> TypeCheck {
+    let mut checker = Checker {
+        program,
+        resolution,
+        vars: vec![],
+        schemes: HashMap::new(),
+        types: HashMap::new(),
+        operands: vec![],
+        errors: vec![],
+    };
+    checker.block(&program.statements);
+
+    // Operators are checked last, uses after them may have pinned their operands down
+    for (id, operands, ty) in std::mem::take(&mut checker.operands) {
+        let found = checker.prune(&ty);
+        if !operands.allows(&found) {
+            let operator = match &program[id] {
+                Expr::Prefix(prefix, _) => prefix_str(prefix),
+                Expr::Infix(_, infix, _) => infix_str(infix),
+                _ => unreachable!("only operators constrain their operands"),
+            };
+            checker.errors.push(TypeError::Operand {
+                operator,
+                found: substitute(&checker.vars, &found),
+                span: program.span(id),
+            });
+        }
+    }
+    let mut errors = checker.errors;
+    errors.sort_by_key(|err| err.span().start.offset);
+    let types = checker
+        .types
+        .iter()
+        .map(|(id, ty)| (*id, substitute(&checker.vars, ty)))
+        .collect();
+    TypeCheck { types, errors }
+}
+
+// Parses and resolves `input` and checks its types, the diagnostics are in source order
+pub fn check_source(input: &str) -> Result<Vec<Diagnostic>, ParseErrors> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let program = parser.parse_program();
+    let errors = parser.get_errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let resolution = resolve(&program);
+    let check = check(&program, &resolution);
+    let mut diagnostics: Vec<Diagnostic> = resolution
+        .errors
+        .iter()
+        .map(ResolveError::to_diagnostic)
+        .chain(check.errors.iter().map(TypeError::to_diagnostic))
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+    Ok(diagnostics)
+}
+
+// Types an operator accepts for its operands
+#[derive(Clone, Copy)]
+enum Operands {
+    Numeric,
+    // Numbers, or strings to concatenate
+    Addable,
+}
+
+impl Operands {
+    // Unknown types are let through, nothing says they are wrong
+    fn allows(self, ty: &Type) -> bool {
+        matches!(
+            (self, ty),
+            (
+                _,
+                Type::Var(_) | Type::Any | Type::Int | Type::BigInt | Type::Float
+            ) | (Operands::Addable, Type::String)
+        )
+    }
+}
+
+// Type of arithmetic on two different number types, `None` when they are not both known numbers
+// or are the same type
+fn promote(left: &Type, right: &Type) -> Option<Type> {
+    match (left, right) {
+        (Type::Int, Type::Float) | (Type::Float, Type::Int) => Some(Type::Float),
+        (Type::Int, Type::BigInt) | (Type::BigInt, Type::Int) => Some(Type::BigInt),
+        _ => None,
+    }
+}
+
+// Type of a `let`, each use gets fresh copies of the variables in `vars`
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+// Follows bound variables to the type they stand for, leaving the parts of it as they are
+fn prune(vars: &[Option<Type>], ty: &Type) -> Type {
+    let mut ty = ty;
+    while let Type::Var(var) = ty {
+        match &vars[*var] {
+            Some(bound) => ty = bound,
+            None => break,
+        }
+    }
+    ty.clone()
+}
+// `ty` with every bound variable in it replaced
+fn substitute(vars: &[Option<Type>], ty: &Type) -> Type {
+    map_type(&prune(vars, ty), &mut |ty| substitute(vars, ty))
+}
+// Applies `f` to the types directly inside `ty`
+fn map_type(ty: &Type, f: &mut impl FnMut(&Type) -> Type) -> Type {
+    match ty {
+        Type::Array(element) => Type::Array(Box::new(f(element))),
+        Type::Hash(key, value) => Type::Hash(Box::new(f(key)), Box::new(f(value))),
+        Type::Function(params, ret) => {
+            Type::Function(params.iter().map(&mut *f).collect(), Box::new(f(ret)))
+        }
+        _ => ty.clone(),
+    }
+}
+// Unbound variables in `ty`, in order of first appearance
+fn free_vars(vars: &[Option<Type>], ty: &Type, out: &mut Vec<usize>) {
+    match prune(vars, ty) {
+        Type::Var(var) if !out.contains(&var) => out.push(var),
+        Type::Array(element) => free_vars(vars, &element, out),
+        Type::Hash(key, value) => {
+            free_vars(vars, &key, out);
+            free_vars(vars, &value, out);
+        }
+        Type::Function(params, ret) => {
+            for param in &params {
+                free_vars(vars, param, out);
+            }
+            free_vars(vars, &ret, out);
+        }
+        _ => {}
+    }
+}
+
+struct Checker<'a> {
+    program: &'a Program,
+    resolution: &'a Resolution,
+    // What each type variable was unified with
+    vars: Vec<Option<Type>>,
+    schemes: HashMap<StmtId, Scheme>,
+    types: HashMap<ExprId, Type>,
+    // Operator expressions and the type of their operands, checked once inference is done
+    operands: Vec<(ExprId, Operands, Type)>,
+    errors: Vec<TypeError>,
+}
+
+impl Checker<'_> {
+    fn fresh(&mut self) -> Type {
+        self.vars.push(None);
+        Type::Var(self.vars.len() - 1)
+    }
+    fn prune(&self, ty: &Type) -> Type {
+        prune(&self.vars, ty)
+    }
+    // Reports `found` at `span` if it cannot be made the same type as `expected`
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) -> bool {
+        if self.unify_types(expected, found) {
+            return true;
+        }
+        self.errors.push(TypeError::Mismatch {
+            expected: substitute(&self.vars, expected),
+            found: substitute(&self.vars, found),
+            span,
+        });
+        false
+    }
+    fn unify_types(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.prune(a), self.prune(b));
+        match (&a, &b) {
+            _ if a == b => true,
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                // Binding a variable to a type containing it would make an infinite type
+                let mut inner = vec![];
+                free_vars(&self.vars, ty, &mut inner);
+                if inner.contains(var) {
+                    return false;
+                }
+                self.vars[*var] = Some(ty.clone());
+                true
+            }
+            (Type::Array(a), Type::Array(b)) => self.unify_types(a, b),
+            (Type::Hash(key_a, value_a), Type::Hash(key_b, value_b)) => {
+                self.unify_types(key_a, key_b) && self.unify_types(value_a, value_b)
+            }
+            (Type::Function(params_a, ret_a), Type::Function(params_b, ret_b)) => {
+                params_a.len() == params_b.len()
+                    && params_a
+                        .iter()
+                        .zip(params_b)
+                        .all(|(a, b)| self.unify_types(a, b))
+                    && self.unify_types(ret_a, ret_b)
+            }
+            _ => false,
+        }
+    }
+    // Lets are only bound to expressions, never to variables a later use could constrain, so
+    // every variable left in the type can be generalized
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let mut vars = vec![];
+        free_vars(&self.vars, ty, &mut vars);
+        Scheme {
+            vars,
+            ty: substitute(&self.vars, ty),
+        }
+    }
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> =
+            scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+        fn replace(ty: &Type, fresh: &HashMap<usize, Type>) -> Type {
+            match ty {
+                Type::Var(var) => fresh.get(var).cloned().unwrap_or(Type::Var(*var)),
+                _ => map_type(ty, &mut |ty| replace(ty, fresh)),
+            }
+        }
+        replace(&scheme.ty, &fresh)
+    }
+
+    // Type of the block's value and the span of the expression producing it
+    fn block(&mut self, block: &[StmtId]) -> (Type, Option<Span>) {
+        let mut value = (Type::Null, None);
+        for id in block {
+            match &self.program[*id] {
+                Statement::Blank => {}
+                Statement::Let(_, annotation, expr) => {
+                    let ty = self.expr(*expr);
+                    let declared = annotation
+                        .as_ref()
+                        .map(|annotation| Type::from_annotation(self.program, annotation));
+                    let ty = match declared {
+                        None => ty,
+                        // The annotation wins, uses see the declared type
+                        Some(Ok(declared)) => {
+                            self.unify(&declared, &ty, self.program.span(*expr));
+                            declared
+                        }
+                        Some(Err(name)) => {
+                            let suggestion =
+                                closest(&name, TYPE_NAMES.iter().copied()).map(String::from);
+                            self.errors.push(TypeError::UnknownType {
+                                name,
+                                span: self.program.span(*id),
+                                suggestion,
+                            });
+                            Type::Any
+                        }
+                    };
+                    let scheme = self.generalize(&ty);
+                    self.schemes.insert(*id, scheme);
+                    value = (Type::Null, None);
+                }
+                Statement::Return(expr) => {
+                    if let Some(expr) = expr {
+                        self.expr(*expr);
+                    }
+                    // Control never reaches the end of the block, any type will do
+                    value = (self.fresh(), None);
+                }
+                Statement::Expr(expr) => {
+                    let ty = self.expr(*expr);
+                    value = (ty, Some(self.program.span(*expr)));
+                }
+            }
+        }
+        value
+    }
+    fn expr(&mut self, id: ExprId) -> Type {
+        let ty = match &self.program[id] {
+            Expr::Ident(_) => {
+                // Every `let` the value may come from has to agree, there are none for builtins
+                // and undeclared names
+                let resolution = self.resolution;
+                let mut ty = None;
+                for definition in resolution.definitions.get(&id).into_iter().flatten() {
+                    let Some(scheme) = self.schemes.get(definition).cloned() else {
+                        continue;
+                    };
+                    let found = self.instantiate(&scheme);
+                    match &ty {
+                        Some(ty) => {
+                            self.unify(ty, &found, self.program.span(id));
+                        }
+                        None => ty = Some(found),
+                    }
+                }
+                ty.unwrap_or_else(|| self.fresh())
+            }
+            Expr::Literal(literal) => match literal {
+                Literal::Int(_) => Type::Int,
+                Literal::BigInt(_) => Type::BigInt,
+                Literal::Float(_) => Type::Float,
+                Literal::Boolean(_) => Type::Bool,
+                Literal::String(_) => Type::String,
+            },
+            Expr::Prefix(prefix, right) => {
+                let right = self.expr(*right);
+                match prefix {
+                    Prefix::Not => Type::Bool,
+                    Prefix::Minus => {
+                        self.operands.push((id, Operands::Numeric, right.clone()));
+                        right
+                    }
+                }
+            }
+            Expr::Infix(left, infix, right) => {
+                let left = self.expr(*left);
+                let span = self.program.span(*right);
+                let right = self.expr(*right);
+                // Numbers of different types are promoted, anything else has to match
+                let (left, unified) = match promote(&self.prune(&left), &self.prune(&right)) {
+                    Some(promoted) => (promoted, true),
+                    None => {
+                        let unified = self.unify(&left, &right, span);
+                        (left, unified)
+                    }
+                };
+                let (operands, ty) = match infix {
+                    Infix::Equal | Infix::NotEqual => (None, Type::Bool),
+                    Infix::LessThan
+                    | Infix::LessThanEqual
+                    | Infix::GreaterThan
+                    | Infix::GreaterThanEqual => (Some(Operands::Numeric), Type::Bool),
+                    Infix::Plus => (Some(Operands::Addable), left.clone()),
+                    Infix::Minus | Infix::Multiply | Infix::Divide => {
+                        (Some(Operands::Numeric), left.clone())
+                    }
+                };
+                // A mismatch was already reported, the operator would only repeat it
+                if let (Some(operands), true) = (operands, unified) {
+                    self.operands.push((id, operands, left));
+                }
+                ty
+            }
+            Expr::If {
+                cond,
+                consequence,
+                alternative,
+            } => {
+                self.expr(*cond);
+                let (consequence, _) = self.block(consequence);
+                match alternative {
+                    Some(alternative) => {
+                        let (alternative, span) = self.block(alternative);
+                        let span = span.unwrap_or_else(|| self.program.span(id));
+                        self.unify(&consequence, &alternative, span);
+                        consequence
+                    }
+                    None => Type::Null,
+                }
+            }
+        };
+        self.types.insert(id, ty.clone());
+        ty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::testing::parse;
+
+    // Types of the values of the top level statements
+    fn types(input: &str) -> Vec<String> {
+        let program = parse(input);
+        let check = check(&program, &resolve(&program));
+        assert_eq!(check.errors, vec![], "{}", input);
+        program
+            .statements
+            .iter()
+            .filter_map(|id| match &program[*id] {
+                Statement::Let(_, _, value) | Statement::Expr(value) => {
+                    Some(check.get(*value).unwrap().to_string())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+    fn errors(input: &str) -> Vec<String> {
+        let program = parse(input);
+        let check = check(&program, &resolve(&program));
+        check.errors.iter().map(|err| err.to_string()).collect()
+    }
+
+    #[test]
+    fn test_infer() {
+        assert_eq!(
+            types("let a = 1 + 2 * 3; let b = a < 4; -2.5; 10n / 2n; !5; a == a"),
+            vec!["int", "bool", "float", "bigint", "bool", "bool"]
+        );
+        assert_eq!(
+            types("let x = if (c) { 1 } else { return 2; }; if (x) { true }; if (x) {} else {}"),
+            vec!["int", "null", "null"]
+        );
+        // `len` is a builtin, nothing is known about it
+        assert_eq!(types("len; let l = len; l"), vec!["t0", "t1", "t2"]);
+    }
+    #[test]
+    fn test_numeric_promotion() {
+        assert_eq!(
+            types("1 + 2.5; 2.5 * 1; 1 + 10n; 10n / 2; 1 < 2.5; 10n == 1"),
+            vec!["float", "float", "bigint", "bigint", "bool", "bool"]
+        );
+        // The promoted type carries on to the next operator
+        assert_eq!(
+            types("let a = 1; let b = a - 0.5; b * a; a + 1n + 2"),
+            vec!["int", "float", "float", "bigint"]
+        );
+        assert_eq!(
+            errors("1n + 2.5; (1 + 2.5) + 1n"),
+            vec![
+                "Type mismatch at 1:6: expected bigint, found float",
+                "Type mismatch at 1:23: expected float, found bigint",
+            ]
+        );
+    }
+    #[test]
+    fn test_let_polymorphism() {
+        // Each use of `f` gets its own copy of the unknown type
+        assert_eq!(
+            types("let f = first; f + 1; f == true; f"),
+            vec!["t0", "int", "bool", "t3"]
+        );
+        // Within one expression the uses of an unknown type are tied together
+        assert_eq!(
+            errors("let f = first; (f + f) == true"),
+            vec!["Operator + at 1:16 cannot be applied to bool"]
+        );
+    }
+    #[test]
+    fn test_annotations() {
+        assert_eq!(
+            types("let a: int = first; a; let b: fn(int) -> [bool] = last; b"),
+            vec!["int", "int", "fn(int) -> [bool]", "fn(int) -> [bool]"]
+        );
+        // `any` takes whatever it is given and goes wherever it is used
+        assert_eq!(
+            types("let a: any = 1; a + true; -a; if (a) { a } else { 2.5 }"),
+            vec!["int", "any", "any", "any"]
+        );
+        assert_eq!(
+            types("let h: {string: [any]} = rest; let f: fn() -> null = rest; f"),
+            vec!["{string: [any]}", "fn() -> null", "fn() -> null"]
+        );
+    }
+    #[test]
+    fn test_errors() {
+        let tests = [
+            ("5 + true", "Type mismatch at 1:5: expected int, found bool"),
+            (
+                "let a = 1n;\na * 2.0",
+                "Type mismatch at 2:5: expected bigint, found float",
+            ),
+            ("-true", "Operator - at 1:1 cannot be applied to bool"),
+            (
+                "let x: int = true",
+                "Type mismatch at 1:14: expected int, found bool",
+            ),
+            (
+                "let x: [int] = 1;",
+                "Type mismatch at 1:16: expected [int], found int",
+            ),
+            (
+                "let x: flaot = 1;",
+                "Unknown type flaot at 1:1, did you mean `float`?",
+            ),
+            (
+                "true < false",
+                "Operator < at 1:1 cannot be applied to bool",
+            ),
+            (
+                "if (c) { 1 } else { false }",
+                "Type mismatch at 1:21: expected int, found bool",
+            ),
+            (
+                "let n = if (c) { 1 };\nn + 1",
+                "Type mismatch at 2:5: expected null, found int",
+            ),
+            // Bound by either `let`
+            (
+                "let a = 1;\nif (c) { let a = true; };\na",
+                "Type mismatch at 3:1: expected bool, found int",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(errors(input), vec![expected], "{}", input);
+        }
+    }
+    #[test]
+    fn test_check_source() {
+        let input = "let x = 1;\nx + true + y";
+        let messages: Vec<String> = check_source(input)
+            .unwrap()
+            .iter()
+            .map(|diagnostic| diagnostic.render(input, "main.mk"))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error: expected int, found bool
+ --> main.mk:2:5
+  |
+2 | x + true + y
+  |     ^^^^",
+                "error: `y` is not declared
+ --> main.mk:2:12
+  |
+2 | x + true + y
+  |            ^",
+            ]
+        );
+    }
+}